@@ -1,2 +1,3 @@
 pub use native;
 pub use niceties;
+pub use signaling;
@@ -0,0 +1,109 @@
+//! Compares two desync reports, one from each side of a desync, and points at the first value
+//! that differs.
+
+use clap::Parser;
+use std::{collections::BTreeMap, fs, path::PathBuf, process::ExitCode};
+
+#[derive(Parser, Debug)]
+struct Args {
+    /// Report written by one peer
+    ours: PathBuf,
+    /// Report written by the other peer for the same frame
+    theirs: PathBuf,
+}
+
+#[derive(Default)]
+struct Report {
+    header: BTreeMap<String, String>,
+    /// Keyed by `Resource.field`.
+    resources: BTreeMap<String, String>,
+    /// Keyed by rollback index, then `Component.field`.
+    values: BTreeMap<(usize, String), String>,
+}
+
+fn parse(path: &PathBuf) -> Result<Report, String> {
+    let text = fs::read_to_string(path).map_err(|err| format!("{}: {err}", path.display()))?;
+    let mut report = Report::default();
+    for (number, line) in text.lines().enumerate() {
+        let Some((key, rest)) = line.split_once(' ') else {
+            continue;
+        };
+        if key == "resource" {
+            let (field, value) = rest.split_once(' ').unwrap_or((rest, ""));
+            report
+                .resources
+                .insert(field.to_string(), value.to_string());
+            continue;
+        }
+        if key != "entity" {
+            report.header.insert(key.to_string(), rest.to_string());
+            continue;
+        }
+        let mut parts = rest.splitn(3, ' ');
+        let (Some(index), Some(field)) = (parts.next(), parts.next()) else {
+            return Err(format!("{}:{}: malformed line", path.display(), number + 1));
+        };
+        let index = index
+            .parse()
+            .map_err(|err| format!("{}:{}: {err}", path.display(), number + 1))?;
+        let value = parts.next().unwrap_or_default().to_string();
+        report.values.insert((index, field.to_string()), value);
+    }
+    Ok(report)
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+    let (ours, theirs) = match (parse(&args.ours), parse(&args.theirs)) {
+        (Ok(ours), Ok(theirs)) => (ours, theirs),
+        (Err(err), _) | (_, Err(err)) => {
+            eprintln!("{err}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    if ours.header.get("frame") != theirs.header.get("frame") {
+        println!(
+            "warning: the reports are for different frames ({} and {})",
+            ours.header.get("frame").map_or("?", String::as_str),
+            theirs.header.get("frame").map_or("?", String::as_str),
+        );
+    }
+
+    let mut fields: Vec<_> = ours
+        .resources
+        .keys()
+        .chain(theirs.resources.keys())
+        .collect();
+    fields.sort();
+    fields.dedup();
+    for field in fields {
+        match (ours.resources.get(field), theirs.resources.get(field)) {
+            (Some(a), Some(b)) if a == b => continue,
+            (Some(a), Some(b)) => println!("resource {field} differs:\n  {a}\n  {b}"),
+            (Some(_), None) => println!("resource {field} is missing from the second report"),
+            (None, Some(_)) => println!("resource {field} is missing from the first report"),
+            (None, None) => unreachable!(),
+        }
+        return ExitCode::FAILURE;
+    }
+
+    let mut keys: Vec<_> = ours.values.keys().chain(theirs.values.keys()).collect();
+    keys.sort();
+    keys.dedup();
+    for key @ (index, field) in keys {
+        match (ours.values.get(key), theirs.values.get(key)) {
+            (Some(a), Some(b)) if a == b => continue,
+            (Some(a), Some(b)) => {
+                println!("entity {index} {field} differs:\n  {a}\n  {b}");
+            }
+            (Some(_), None) => println!("entity {index} {field} is missing from the second report"),
+            (None, Some(_)) => println!("entity {index} {field} is missing from the first report"),
+            (None, None) => unreachable!(),
+        }
+        return ExitCode::FAILURE;
+    }
+
+    println!("no differences in the recorded resources and components");
+    ExitCode::SUCCESS
+}
@@ -1,15 +1,30 @@
 use bevy::prelude::*;
 use bevy::window::WindowResolution;
-use clap::{Parser, ValueEnum};
+use clap::{builder::RangedU64ValueParser, CommandFactory, FromArgMatches, Parser, ValueEnum};
+use std::{ops::RangeInclusive, path::PathBuf};
 
 #[derive(ValueEnum, Clone)]
-enum Side {
+pub enum Side {
     Left,
     Right,
 }
 
+/// See `DelayPolicy` in the game's settings.
+#[derive(ValueEnum, Clone, Copy)]
+pub enum DelayPolicy {
+    MinimizeRollbacks,
+    MinimizeLatency,
+}
+
+#[derive(ValueEnum, Clone, Copy)]
+pub enum Difficulty {
+    Easy,
+    Normal,
+    Hard,
+}
+
 #[derive(Clone)]
-struct Resolution(u32, u32);
+pub struct Resolution(pub u32, pub u32);
 
 impl std::fmt::Display for Resolution {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
@@ -22,12 +37,12 @@ fn parse_resolution(
 ) -> Result<Resolution, Box<dyn std::error::Error + Send + Sync + 'static>> {
     let pos = s
         .find(',')
-        .ok_or_else(|| format!("Resolution not in \"<width>,<height>\" format"))?;
+        .ok_or_else(|| "Resolution not in \"<width>,<height>\" format".to_string())?;
     Ok(Resolution(s[..pos].parse()?, s[pos + 1..].parse()?))
 }
 
 #[derive(Parser, Resource, Clone)]
-struct Args {
+pub struct Args {
     #[arg(
         long,
         short,
@@ -45,6 +60,110 @@ struct Args {
         value_name = "WIDTH,HEIGHT",
         default_value_t = Resolution(3456, 2234))]
     pub resolution: Resolution,
+    #[arg(
+        long,
+        help = "Matchbox signaling server to matchmake through",
+        value_name = "URL",
+        default_value = "ws://127.0.0.1:3536"
+    )]
+    pub signaling: String,
+    #[arg(
+        long,
+        help = "Start a signaling server in the background unless one is already listening at --signaling"
+    )]
+    pub start_signaling: bool,
+    #[arg(
+        long,
+        help = "Room code to join, instead of picking one in the lobby",
+        value_name = "CODE"
+    )]
+    pub room: Option<String>,
+    #[arg(
+        long,
+        short,
+        help = "How many players to wait for before starting",
+        value_name = "N",
+        default_value_t = 2
+    )]
+    pub players: usize,
+    #[arg(
+        long,
+        help = "How many of the room's peers are spectators, on top of the players",
+        value_name = "N",
+        default_value_t = 0
+    )]
+    pub spectators: usize,
+    #[arg(
+        long,
+        conflicts_with = "synctest",
+        help = "Take one of the room's spectator slots and watch instead of playing"
+    )]
+    pub spectate: bool,
+    #[arg(
+        long,
+        conflicts_with_all = ["synctest", "spectate"],
+        help = "Skip matchmaking and play split-screen on this machine, one key map per player (up to 4)"
+    )]
+    pub local: bool,
+    #[arg(
+        long,
+        value_enum,
+        conflicts_with_all = ["synctest", "spectate", "local"],
+        help = "Skip matchmaking and practice against AI ships of this difficulty",
+        value_name = "DIFFICULTY"
+    )]
+    pub practice: Option<Difficulty>,
+    #[arg(
+        long,
+        help = "Write each match's inputs to FILE, to watch again with --replay",
+        value_name = "FILE"
+    )]
+    pub record: Option<PathBuf>,
+    #[arg(
+        long,
+        conflicts_with_all = ["synctest", "spectate", "local", "practice", "record"],
+        help = "Skip matchmaking and watch a match recorded with --record",
+        value_name = "FILE"
+    )]
+    pub replay: Option<PathBuf>,
+    #[arg(
+        long,
+        help = "Skip matchmaking and run a local GGRS SyncTest session, resimulating CHECK_DISTANCE frames every frame",
+        value_name = "CHECK_DISTANCE"
+    )]
+    pub synctest: Option<usize>,
+    #[arg(
+        long,
+        requires = "synctest",
+        help = "Feed the SyncTest session random inputs instead of the keyboard"
+    )]
+    pub random_inputs: bool,
+    #[arg(
+        long,
+        help = "Never delay inputs by fewer frames than this",
+        value_name = "FRAMES",
+        default_value_t = 0
+    )]
+    pub min_input_delay: usize,
+    #[arg(
+        long,
+        help = "Never delay inputs by more frames than this",
+        value_name = "FRAMES",
+        default_value_t = 8
+    )]
+    pub max_input_delay: usize,
+    #[arg(
+        long,
+        value_enum,
+        help = "How to pick the input delay from the round trip time measured while matchmaking",
+        default_value_t = DelayPolicy::MinimizeRollbacks
+    )]
+    pub delay_policy: DelayPolicy,
+    #[arg(
+        long,
+        help = "Keep a report of every frame, to write the first desynced one to a file"
+    )]
+    pub desync_reports: bool,
 }
 
 impl Default for Args {
@@ -52,15 +171,59 @@ impl Default for Args {
         Self {
             side: Side::Left,
             resolution: Resolution(3456, 2234),
+            signaling: "ws://127.0.0.1:3536".to_string(),
+            start_signaling: false,
+            room: None,
+            players: 2,
+            spectators: 0,
+            spectate: false,
+            local: false,
+            practice: None,
+            record: None,
+            replay: None,
+            synctest: None,
+            random_inputs: false,
+            min_input_delay: 0,
+            max_input_delay: 8,
+            delay_policy: DelayPolicy::MinimizeRollbacks,
+            desync_reports: false,
         }
     }
 }
 
-pub struct SizedWindowPlugin;
+impl Args {
+    /// Parses the command line, with the ranges of player counts and check distances the game
+    /// takes, exiting with a usage error outside them.
+    pub fn parse_within(
+        players: RangeInclusive<usize>,
+        check_distance: RangeInclusive<usize>,
+    ) -> Self {
+        let range = |range: &RangeInclusive<usize>| {
+            RangedU64ValueParser::<usize>::new().range(*range.start() as u64..=*range.end() as u64)
+        };
+        let matches = Args::command()
+            .mut_arg("players", |arg| {
+                arg.value_parser(range(&players)).help(format!(
+                    "How many players to wait for before starting ({} to {})",
+                    players.start(),
+                    players.end()
+                ))
+            })
+            .mut_arg("synctest", |arg| arg.value_parser(range(&check_distance)))
+            .get_matches();
+        Args::from_arg_matches(&matches).unwrap_or_else(|err| err.exit())
+    }
+}
+
+/// Opens the window and inserts the command line [`Args`], parsed with the given ranges.
+pub struct SizedWindowPlugin {
+    pub players: RangeInclusive<usize>,
+    pub check_distance: RangeInclusive<usize>,
+}
 
 impl Plugin for SizedWindowPlugin {
     fn build(&self, app: &mut App) {
-        let args = Args::parse();
+        let args = Args::parse_within(self.players.clone(), self.check_distance.clone());
         // FIXME fussing with resolution
         let (width, height) = (
             args.resolution.0 as f32 / 2.5,
@@ -77,6 +240,7 @@ impl Plugin for SizedWindowPlugin {
                 ..default()
             }),
             ..default()
-        }));
+        }))
+        .insert_resource(args);
     }
 }
@@ -0,0 +1,212 @@
+//! A matchbox signaling server for development, with the room semantics of `matchbox_server`:
+//! peers connecting to `/<room>` all meet each other, peers connecting to `/<room>?next=N` are
+//! grouped N at a time, each group in a room of its own.
+//!
+//! One thread per peer with blocking sockets, as it only ever serves a handful of peers.
+
+use matchbox_protocol::{JsonPeerEvent, JsonPeerRequest, PeerEvent, PeerId, PeerRequest};
+use std::{
+    collections::HashMap,
+    io::{self, ErrorKind},
+    net::{TcpListener, TcpStream, ToSocketAddrs},
+    sync::{mpsc, Arc, Mutex},
+    thread,
+    time::Duration,
+};
+use tungstenite::{
+    error::ProtocolError,
+    handshake::server::{Request, Response},
+    Message, WebSocket,
+};
+use uuid::Uuid;
+
+pub const DEFAULT_ADDRESS: &str = "127.0.0.1:3536";
+
+/// How long a peer's thread waits for its socket before passing on other peers' events.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+struct Room {
+    name: String,
+    next: Option<usize>,
+    /// Which of the `next` groups, counting those already filled.
+    group: usize,
+}
+
+#[derive(Default)]
+struct State {
+    /// Where to send each connected peer's events.
+    peers: HashMap<PeerId, mpsc::Sender<String>>,
+    rooms: HashMap<Room, Vec<PeerId>>,
+    /// How many groups of each `?next=N` room have filled up.
+    filled: HashMap<(String, usize), usize>,
+}
+
+impl State {
+    fn send(&self, peer: PeerId, event: JsonPeerEvent) {
+        if let Some(sender) = self.peers.get(&peer) {
+            // the peer's thread may just be leaving
+            let _ = sender.send(event.to_string());
+        }
+    }
+
+    /// Adds the peer to the room or, for `?next=N`, its group still waiting for players, and
+    /// tells everyone already there. They start the connection to the newcomer.
+    fn join(
+        &mut self,
+        id: PeerId,
+        name: String,
+        next: Option<usize>,
+        sender: mpsc::Sender<String>,
+    ) -> Room {
+        let group = next.map_or(0, |next| {
+            *self.filled.get(&(name.clone(), next)).unwrap_or(&0)
+        });
+        let room = Room { name, next, group };
+        let peers = self.rooms.entry(room.clone()).or_default();
+        let others = peers.clone();
+        peers.push(id);
+        if let Some(next) = next.filter(|&next| peers.len() >= next) {
+            *self.filled.entry((room.name.clone(), next)).or_default() += 1;
+        }
+        self.peers.insert(id, sender);
+        for other in others {
+            self.send(other, PeerEvent::NewPeer(id));
+        }
+        room
+    }
+
+    fn leave(&mut self, id: PeerId, room: &Room) {
+        self.peers.remove(&id);
+        let Some(peers) = self.rooms.get_mut(room) else {
+            return;
+        };
+        peers.retain(|&peer| peer != id);
+        let others = peers.clone();
+        if others.is_empty() {
+            self.rooms.remove(room);
+        }
+        for other in others {
+            self.send(other, PeerEvent::PeerLeft(id));
+        }
+    }
+}
+
+/// Splits `/<room>?next=N` into the room name and `N`.
+fn parse_path(path: &str) -> (String, Option<usize>) {
+    let (name, query) = path.split_once('?').unwrap_or((path, ""));
+    let next = query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|&(key, _)| key == "next")
+        .and_then(|(_, value)| value.parse().ok());
+    (name.trim_start_matches('/').to_string(), next)
+}
+
+// tungstenite's error types are what they are
+#[allow(clippy::result_large_err)]
+fn handle_peer(stream: TcpStream, state: Arc<Mutex<State>>) {
+    let mut path = String::new();
+    let callback = |request: &Request, response: Response| {
+        path = request.uri().to_string();
+        Ok(response)
+    };
+    let mut socket = match tungstenite::accept_hdr(stream, callback) {
+        Ok(socket) => socket,
+        Err(err) => {
+            log::warn!("Handshake failed: {err}");
+            return;
+        }
+    };
+    let (name, next) = parse_path(&path);
+
+    let id = PeerId(Uuid::new_v4());
+    if let Err(err) = socket.send(Message::Text(PeerEvent::IdAssigned(id).to_string())) {
+        log::warn!("Failed to assign an id: {err}");
+        return;
+    }
+    let (sender, events) = mpsc::channel();
+    let room = state.lock().unwrap().join(id, name, next, sender);
+    log::info!("{id} joined {room:?}");
+
+    match relay(&mut socket, id, &events, &state) {
+        Ok(())
+        | Err(tungstenite::Error::ConnectionClosed)
+        | Err(tungstenite::Error::Protocol(ProtocolError::ResetWithoutClosingHandshake)) => {}
+        Err(err) => log::warn!("{id} dropped: {err}"),
+    }
+    state.lock().unwrap().leave(id, &room);
+    log::info!("{id} left {room:?}");
+}
+
+/// Forwards the peer's signals to their receivers, and other peers' events to the peer, until
+/// it disconnects.
+#[allow(clippy::result_large_err)]
+fn relay(
+    socket: &mut WebSocket<TcpStream>,
+    id: PeerId,
+    events: &mpsc::Receiver<String>,
+    state: &Mutex<State>,
+) -> tungstenite::Result<()> {
+    socket.get_ref().set_read_timeout(Some(POLL_INTERVAL))?;
+    loop {
+        match socket.read() {
+            Ok(Message::Text(text)) => match text.parse::<JsonPeerRequest>() {
+                Ok(PeerRequest::Signal { receiver, data }) => {
+                    let signal = PeerEvent::Signal { sender: id, data };
+                    state.lock().unwrap().send(receiver, signal);
+                }
+                Ok(PeerRequest::KeepAlive) => {}
+                Err(err) => log::warn!("{id} sent a bad request: {err}"),
+            },
+            Ok(Message::Close(_)) => return Ok(()),
+            Ok(_) => {}
+            Err(tungstenite::Error::Io(err))
+                if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+            Err(err) => return Err(err),
+        }
+        for event in events.try_iter() {
+            socket.send(Message::Text(event))?;
+        }
+    }
+}
+
+/// Serves peers until the listener fails.
+pub fn serve(listener: TcpListener) -> io::Result<()> {
+    let state = Arc::default();
+    loop {
+        let (stream, _) = listener.accept()?;
+        let state = Arc::clone(&state);
+        thread::spawn(move || handle_peer(stream, state));
+    }
+}
+
+/// The `host:port` of a `ws://host:port/...` URL.
+pub fn address(url: &str) -> &str {
+    let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+    rest.split('/').next().unwrap_or_default()
+}
+
+/// Starts a server on a background thread, unless something already accepts connections on
+/// the address of `url`. Returns whether it started one.
+pub fn start_unless_running(url: &str) -> io::Result<bool> {
+    let address = address(url);
+    let running = address
+        .to_socket_addrs()?
+        .any(|addr| TcpStream::connect_timeout(&addr, Duration::from_millis(200)).is_ok());
+    if running {
+        return Ok(false);
+    }
+    let listener = match TcpListener::bind(address) {
+        Ok(listener) => listener,
+        // another window got there first
+        Err(err) if err.kind() == ErrorKind::AddrInUse => return Ok(false),
+        Err(err) => return Err(err),
+    };
+    thread::spawn(move || {
+        if let Err(err) = serve(listener) {
+            log::error!("Signaling server stopped: {err}");
+        }
+    });
+    Ok(true)
+}
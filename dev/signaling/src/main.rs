@@ -0,0 +1,47 @@
+//! Runs the development signaling server in the foreground.
+
+use clap::Parser;
+use std::{net::TcpListener, process::ExitCode};
+
+#[derive(Parser, Debug)]
+struct Args {
+    /// Address to listen on
+    #[arg(long, default_value = signaling::DEFAULT_ADDRESS)]
+    address: String,
+}
+
+/// Prints the server's log to stderr, as nothing else is there to show it.
+struct StderrLogger;
+
+impl log::Log for StderrLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.target().starts_with("signaling")
+    }
+
+    fn log(&self, record: &log::Record) {
+        if self.enabled(record.metadata()) {
+            eprintln!("{} {}", record.level(), record.args());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+    log::set_logger(&StderrLogger).unwrap();
+    log::set_max_level(log::LevelFilter::Info);
+
+    let listener = match TcpListener::bind(&args.address) {
+        Ok(listener) => listener,
+        Err(err) => {
+            eprintln!("Can't listen on {}: {err}", args.address);
+            return ExitCode::FAILURE;
+        }
+    };
+    eprintln!("Listening on {}", args.address);
+    if let Err(err) = signaling::serve(listener) {
+        eprintln!("{err}");
+    }
+    ExitCode::FAILURE
+}
@@ -0,0 +1,129 @@
+//! Opponents for practice: every handle but the first is steered by a controller that only
+//! looks at the simulation, so it produces the same inputs whenever it sees the same frame.
+
+use crate::{Config, Controls, Difficulty, Mode, Player, Settings, ShipInput, AXIS_MAX, FIRE};
+use bevy::{prelude::*, utils::HashMap};
+use bevy_ggrs::{LocalInputs, LocalPlayers, RollbackFrameCount};
+
+/// The handle at the keyboard and gamepads.
+const HUMAN: usize = 0;
+
+struct Skill {
+    /// How far off target, in radians, before turning. Sloppier ships wobble around their aim.
+    turn_deadzone: f32,
+    /// How far off target, in radians, a ship is willing to fire.
+    aim_tolerance: f32,
+    /// Frames between decisions. Inputs are held in between, and a ship fires at most once
+    /// per decision.
+    reaction_frames: i32,
+}
+
+impl Difficulty {
+    fn skill(self) -> Skill {
+        match self {
+            Difficulty::Easy => Skill {
+                turn_deadzone: 0.15,
+                aim_tolerance: 0.2,
+                reaction_frames: 20,
+            },
+            Difficulty::Normal => Skill {
+                turn_deadzone: 0.06,
+                aim_tolerance: 0.1,
+                reaction_frames: 10,
+            },
+            Difficulty::Hard => Skill {
+                turn_deadzone: 0.02,
+                aim_tolerance: 0.05,
+                reaction_frames: 4,
+            },
+        }
+    }
+}
+
+/// Turns toward `target` and fires once lined up.
+fn steer(ship: &Transform, target: Vec3, skill: &Skill) -> ShipInput {
+    let forward = -ship.local_z();
+    let Some(to_target) = (target - ship.translation).try_normalize() else {
+        return ShipInput::default();
+    };
+    // `rotate_players` turns about the world axes: x rolls, y yaws and z pitches
+    let turn = forward.cross(to_target);
+    let full_turn = |amount: f32| {
+        if amount > skill.turn_deadzone.sin() {
+            AXIS_MAX
+        } else if amount < -skill.turn_deadzone.sin() {
+            -AXIS_MAX
+        } else {
+            0
+        }
+    };
+    let mut input = ShipInput {
+        pitch: full_turn(turn.z),
+        yaw: full_turn(turn.y),
+        roll: full_turn(turn.x),
+        ..default()
+    };
+    // `cross` can't tell dead ahead from right behind
+    if forward.dot(to_target) < 0. && input == ShipInput::default() {
+        input.yaw = AXIS_MAX;
+    }
+    if forward.angle_between(to_target) < skill.aim_tolerance {
+        input.buttons |= FIRE;
+    }
+    input
+}
+
+pub fn read_practice_inputs(
+    mut commands: Commands,
+    controls: Controls,
+    local_players: Res<LocalPlayers>,
+    settings: Res<Settings>,
+    frame: Res<RollbackFrameCount>,
+    players: Query<(&Transform, &Player)>,
+    mut held: Local<HashMap<usize, ShipInput>>,
+) {
+    let Mode::Practice { difficulty } = settings.mode else {
+        return;
+    };
+    let skill = difficulty.skill();
+    let frame = i32::from(*frame);
+    if frame == 0 {
+        // a new match
+        held.clear();
+    }
+    let mut local_inputs = HashMap::new();
+
+    for &handle in &local_players.0 {
+        if handle == HUMAN {
+            local_inputs.insert(handle, controls.input());
+            continue;
+        }
+        // stagger the ships so they don't all react on the same frame
+        if (frame + handle as i32) % skill.reaction_frames != 0 {
+            let mut input = held.get(&handle).copied().unwrap_or_default();
+            input.buttons &= !FIRE;
+            local_inputs.insert(handle, input);
+            continue;
+        }
+        let Some((ship, _)) = players.iter().find(|(_, player)| player.handle == handle) else {
+            local_inputs.insert(handle, ShipInput::default());
+            continue;
+        };
+        let target = players
+            .iter()
+            .filter(|(_, player)| player.handle != handle)
+            .min_by(|(a, a_player), (b, b_player)| {
+                let a_distance = a.translation.distance_squared(ship.translation);
+                let b_distance = b.translation.distance_squared(ship.translation);
+                a_distance
+                    .total_cmp(&b_distance)
+                    .then(a_player.handle.cmp(&b_player.handle))
+            });
+        let input = target.map_or(ShipInput::default(), |(target, _)| {
+            steer(ship, target.translation, &skill)
+        });
+        held.insert(handle, input);
+        local_inputs.insert(handle, input);
+    }
+    commands.insert_resource(LocalInputs::<Config>(local_inputs));
+}
@@ -0,0 +1,272 @@
+//! Runs the simulation, Rapier included, with `MinimalPlugins`, without a window or a renderer,
+//! and prints the checksum of every frame followed by the final state. Exits with an error if
+//! resimulating a frame changed its checksum.
+//!
+//! ```text
+//! headless <replay or script> [--frames N] [--check-distance N]
+//! ```
+//!
+//! `--frames` is the last frame to run, the last one the input covers by default, and
+//! `--check-distance` how many frames SyncTest resimulates every frame, 2 to 7.
+//!
+//! The input is either a replay written with `--record` or a script, one line per span of
+//! frames with every handle's input as in replays: the bytes of its `ShipInput` in hex, that is
+//! pitch, yaw, roll, throttle and buttons. Frames not covered by the script get no input.
+//!
+//! ```text
+//! # comments and blank lines are skipped
+//! players 2
+//! # the Rng seed, 0 if not given
+//! seed 7
+//! # handle 0 yaws left, handle 1 idles
+//! 1..60 007f000000 0000000000
+//! # handle 0 fires
+//! 60 0000000001 0000000000
+//! ```
+
+use bevy::{prelude::*, time::TimeUpdateStrategy};
+use bevy_ggrs::{
+    prelude::*, Checksum, LocalInputs, LocalPlayers, RollbackFrameCount, SaveWorld, SaveWorldSet,
+};
+use mass_networking::*;
+use std::{collections::BTreeMap, fs, process::ExitCode, time::Duration};
+
+#[derive(Resource)]
+struct Inputs(Replay);
+
+/// The checksum each frame had the first time it was saved, and whether any later save of it
+/// disagreed.
+#[derive(Resource, Default)]
+struct Checksums {
+    frames: BTreeMap<i32, u128>,
+    mismatch: bool,
+}
+
+fn parse_script(text: &str) -> Result<Replay, String> {
+    let mut replay = Replay {
+        fps: FPS,
+        ..default()
+    };
+    for (number, line) in text.lines().enumerate() {
+        let error = |message: &str| format!("line {}: {message}", number + 1);
+        let line = line.split('#').next().unwrap().trim();
+        let mut words = line.split_whitespace();
+        let Some(frames) = words.next() else {
+            continue;
+        };
+        if frames == "seed" {
+            replay.seed = words
+                .next()
+                .and_then(|seed| seed.parse().ok())
+                .ok_or_else(|| error("bad seed"))?;
+            continue;
+        }
+        if frames == "players" {
+            replay.players = words
+                .next()
+                .and_then(|players| players.parse().ok())
+                .ok_or_else(|| error("bad player count"))?;
+            continue;
+        }
+        let (start, end) = match frames.split_once("..") {
+            Some((start, end)) => (start.parse(), end.parse()),
+            None => (frames.parse(), frames.parse().map(|frame: i32| frame + 1)),
+        };
+        let (Ok(start), Ok(end)) = (start, end) else {
+            return Err(error("bad frame range"));
+        };
+        let inputs = words
+            .map(str::parse)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|err: String| error(&err))?;
+        if inputs.len() != replay.players {
+            return Err(error("wrong number of inputs"));
+        }
+        for frame in start..end {
+            replay.frames.insert(frame, inputs.clone());
+        }
+    }
+    Ok(replay)
+}
+
+fn spawn_players(mut commands: Commands, inputs: Res<Inputs>) {
+    let players = inputs.0.players;
+    for handle in 0..players {
+        commands
+            .spawn((
+                ship_body(),
+                Player { handle },
+                TransformBundle::from_transform(spawn_transform(handle, players)),
+            ))
+            .add_rollback();
+    }
+}
+
+fn read_inputs(
+    mut commands: Commands,
+    local_players: Res<LocalPlayers>,
+    frame: Res<RollbackFrameCount>,
+    inputs: Res<Inputs>,
+) {
+    // the inputs read now are simulated in the next frame
+    let frame_inputs = inputs.0.frames.get(&(i32::from(*frame) + 1));
+    let local_inputs = local_players
+        .0
+        .iter()
+        .map(|&handle| {
+            let input = frame_inputs.and_then(|inputs| inputs.get(handle).copied());
+            (handle, input.unwrap_or_default())
+        })
+        .collect();
+    commands.insert_resource(LocalInputs::<Config>(local_inputs));
+}
+
+fn print_checksums(
+    frame: Res<RollbackFrameCount>,
+    checksum: Res<Checksum>,
+    mut checksums: ResMut<Checksums>,
+) {
+    let frame = i32::from(*frame);
+    match checksums.frames.get(&frame) {
+        None => {
+            println!("frame {frame} {:032x}", checksum.0);
+            checksums.frames.insert(frame, checksum.0);
+        }
+        Some(&first) if first != checksum.0 => {
+            println!(
+                "frame {frame} resimulated to {:032x}, it was first {first:032x}",
+                checksum.0
+            );
+            checksums.mismatch = true;
+        }
+        Some(_) => {}
+    }
+}
+
+const USAGE: &str = "usage: headless <replay or script> [--frames N] [--check-distance N]";
+
+fn main() -> ExitCode {
+    let mut args = std::env::args().skip(1);
+    let mut path = None;
+    let mut frames = None;
+    let mut check_distance = MIN_CHECK_DISTANCE;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--frames" => match args.next().map(|frames| frames.parse()) {
+                Some(Ok(last)) if last >= 0 => frames = Some(last),
+                _ => {
+                    eprintln!("--frames takes the last frame to run, 0 or more\n{USAGE}");
+                    return ExitCode::FAILURE;
+                }
+            },
+            "--check-distance" => match args.next().map(|n| n.parse()) {
+                Some(Ok(n)) if (MIN_CHECK_DISTANCE..=MAX_CHECK_DISTANCE).contains(&n) => {
+                    check_distance = n
+                }
+                _ => {
+                    eprintln!(
+                        "--check-distance takes {MIN_CHECK_DISTANCE} to {MAX_CHECK_DISTANCE} \
+                         frames\n{USAGE}"
+                    );
+                    return ExitCode::FAILURE;
+                }
+            },
+            _ => path = Some(arg),
+        }
+    }
+    let Some(path) = path else {
+        eprintln!("{USAGE}");
+        return ExitCode::FAILURE;
+    };
+
+    let inputs = match fs::read_to_string(&path) {
+        Ok(text) if Replay::is_replay(&text) => Replay::parse(&text),
+        Ok(text) => parse_script(&text),
+        Err(err) => Err(err.to_string()),
+    };
+    let inputs = match inputs {
+        Ok(inputs) if inputs.players > 0 => inputs,
+        Ok(_) => {
+            eprintln!("{path}: no players");
+            return ExitCode::FAILURE;
+        }
+        Err(err) => {
+            eprintln!("{path}: {err}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let frames = frames
+        .or_else(|| inputs.frames.keys().next_back().copied())
+        .unwrap_or_default()
+        .max(0);
+
+    let session = match session_builder()
+        .with_num_players(inputs.players)
+        .with_check_distance(check_distance)
+        .start_synctest_session()
+    {
+        Ok(session) => session,
+        Err(err) => {
+            eprintln!("failed to start synctest session: {err}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let mut app = App::new();
+    app.add_plugins((
+        MinimalPlugins,
+        GgrsPlugin::<Config>::default(),
+        SimulationPlugin,
+    ))
+    // a hair over one frame per update, as GGRS only steps once strictly more than a frame
+    // has passed
+    .insert_resource(TimeUpdateStrategy::ManualDuration(
+        Duration::from_secs_f64(1. / FPS as f64) + Duration::from_micros(1),
+    ))
+    .init_resource::<Checksums>()
+    .insert_resource(Rng::new(inputs.seed))
+    .insert_resource(Inputs(inputs))
+    .add_systems(Startup, spawn_players)
+    .add_systems(ReadInputs, read_inputs)
+    .add_systems(SaveWorld, print_checksums.after(SaveWorldSet::Snapshot))
+    .insert_resource(Session::SyncTest(session));
+
+    app.finish();
+    app.cleanup();
+    // The state of a frame is saved, and checksummed, at the start of the next update. Once
+    // resimulating changed a checksum, GGRS stops advancing.
+    loop {
+        let checksums = app.world.resource::<Checksums>();
+        if checksums.mismatch || checksums.frames.contains_key(&frames) {
+            break;
+        }
+        app.update();
+    }
+
+    let mut players: Vec<_> = app
+        .world
+        .query::<(&Player, &Transform)>()
+        .iter(&app.world)
+        .map(|(player, transform)| (player.handle, *transform))
+        .collect();
+    players.sort_by_key(|&(handle, _)| handle);
+    for (handle, transform) in players {
+        let Vec3 { x, y, z } = transform.translation;
+        let [qx, qy, qz, qw] = transform.rotation.to_array();
+        println!(
+            "player {handle} translation {x:?} {y:?} {z:?} rotation {qx:?} {qy:?} {qz:?} {qw:?}"
+        );
+    }
+    let projectiles = app
+        .world
+        .query_filtered::<(), With<Projectile>>()
+        .iter(&app.world)
+        .count();
+    println!("projectiles {projectiles}");
+
+    if app.world.resource::<Checksums>().mismatch {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
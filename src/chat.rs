@@ -0,0 +1,144 @@
+//! Text chat in the lobby and in game, on [`CHAT_CHANNEL`]. Messages are plain UTF-8 and never
+//! touch the rollback inputs.
+
+use crate::CHAT_CHANNEL;
+use bevy::prelude::*;
+use bevy_matchbox::prelude::*;
+use std::{collections::VecDeque, time::Duration};
+
+pub const CHAT_KEY: KeyCode = KeyCode::T;
+const SEND_KEY: KeyCode = KeyCode::Return;
+const MAX_LENGTH: usize = 200;
+const MAX_LINES: usize = 8;
+/// How long a message stays up once the chat is closed.
+const FADE: Duration = Duration::from_secs(10);
+
+/// The latest messages, oldest first, with when they arrived.
+#[derive(Resource, Default)]
+pub struct ChatLog(VecDeque<(Duration, String)>);
+
+/// The message being typed, while the chat is open. Keys go to it instead of the ship.
+#[derive(Resource, Default)]
+pub struct ChatInput(Option<String>);
+
+#[derive(Component)]
+pub struct ChatText;
+
+impl ChatInput {
+    pub fn is_typing(&self) -> bool {
+        self.0.is_some()
+    }
+}
+
+pub fn chatting(input: Res<ChatInput>) -> bool {
+    input.is_typing()
+}
+
+impl ChatLog {
+    fn push(&mut self, now: Duration, line: String) {
+        self.0.push_back((now, line));
+        if self.0.len() > MAX_LINES {
+            self.0.pop_front();
+        }
+    }
+}
+
+fn truncate(text: &str) -> String {
+    text.chars().take(MAX_LENGTH).collect()
+}
+
+pub fn receive_chat(
+    mut socket: ResMut<MatchboxSocket<MultipleChannels>>,
+    mut log: ResMut<ChatLog>,
+    time: Res<Time<Real>>,
+) {
+    for (peer, packet) in socket.channel(CHAT_CHANNEL).receive() {
+        let text = truncate(&String::from_utf8_lossy(&packet));
+        log.push(
+            time.elapsed(),
+            format!("{}: {text}", &peer.to_string()[..8]),
+        );
+    }
+}
+
+pub fn type_chat(
+    mut characters: EventReader<ReceivedCharacter>,
+    keys: Res<Input<KeyCode>>,
+    mut input: ResMut<ChatInput>,
+    mut socket: ResMut<MatchboxSocket<MultipleChannels>>,
+    mut log: ResMut<ChatLog>,
+    time: Res<Time<Real>>,
+) {
+    let Some(message) = &mut input.0 else {
+        // the key that opens the chat isn't part of the message
+        characters.clear();
+        if keys.just_pressed(CHAT_KEY) {
+            input.0 = Some(String::new());
+        }
+        return;
+    };
+
+    for character in characters.read() {
+        if !character.char.is_control() && message.chars().count() < MAX_LENGTH {
+            message.push(character.char);
+        }
+    }
+    if keys.just_pressed(KeyCode::Back) {
+        message.pop();
+    }
+    if keys.just_pressed(SEND_KEY) {
+        let message = input.0.take().unwrap();
+        if message.trim().is_empty() {
+            return;
+        }
+        let peers: Vec<_> = socket.connected_peers().collect();
+        for peer in peers {
+            let packet = message.as_bytes().into();
+            socket.channel(CHAT_CHANNEL).send(packet, peer);
+        }
+        log.push(time.elapsed(), format!("you: {message}"));
+    }
+}
+
+pub fn show_chat(
+    mut commands: Commands,
+    log: Res<ChatLog>,
+    input: Res<ChatInput>,
+    time: Res<Time<Real>>,
+    mut texts: Query<&mut Text, With<ChatText>>,
+) {
+    let mut lines: Vec<_> = log
+        .0
+        .iter()
+        .filter(|(received, _)| input.0.is_some() || time.elapsed() - *received < FADE)
+        .map(|(_, line)| line.clone())
+        .collect();
+    match &input.0 {
+        Some(message) => lines.push(format!("> {message}_")),
+        None if !lines.is_empty() => lines.push(format!("{CHAT_KEY:?} to chat")),
+        None => {}
+    }
+    let value = lines.join("\n");
+
+    if let Ok(mut text) = texts.get_single_mut() {
+        text.sections[0].value = value;
+    } else {
+        commands.spawn((
+            ChatText,
+            TextBundle::from_section(
+                value,
+                TextStyle {
+                    font_size: 18.,
+                    color: Color::WHITE,
+                    ..default()
+                },
+            )
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                bottom: Val::Px(12.),
+                right: Val::Px(12.),
+                ..default()
+            }),
+        ));
+    }
+}
@@ -6,30 +6,67 @@ pub struct Player {
     pub handle: usize,
 }
 
-#[derive(Component)]
+#[derive(Component, Clone, Copy)]
 pub struct Projectile;
 
 #[derive(Component, Clone, Copy)]
 pub struct MoveDir(pub Vec3);
 
-pub fn checksum_transform(transform: &Transform) -> u64 {
+/// Frames left before the entity is despawned.
+#[derive(Component, Clone, Copy)]
+pub struct EntityTTL(pub u32);
+
+/// Where a projectile hit something.
+#[derive(Component)]
+pub struct Explosion;
+
+/// Gameplay state that goes into the checksum GGRS compares between peers. Implementations
+/// hash what the simulation reads and nothing else, the same on every platform: integers widened
+/// to `u64`, since `usize` differs between wasm and native, and floats by their bits.
+pub trait StableHash {
+    fn stable_hash(&self, hasher: &mut impl Hasher);
+}
+
+/// For `checksum_component` and `checksum_resource`. bevy_ggrs combines the hashes of all
+/// entities so that their order doesn't matter.
+pub fn checksum<T: StableHash>(value: &T) -> u64 {
     let mut hasher = FixedState.build_hasher();
+    value.stable_hash(&mut hasher);
+    hasher.finish()
+}
 
+pub(crate) fn hash_floats(floats: &[f32], hasher: &mut impl Hasher) {
     assert!(
-        transform.translation.is_finite() && transform.rotation.is_finite(),
+        floats.iter().all(|float| float.is_finite()),
         "Hashing is not stable for NaN f32 values."
     );
+    for float in floats {
+        float.to_bits().hash(hasher);
+    }
+}
 
-    transform.translation.x.to_bits().hash(&mut hasher);
-    transform.translation.y.to_bits().hash(&mut hasher);
-    transform.translation.z.to_bits().hash(&mut hasher);
+impl StableHash for Transform {
+    fn stable_hash(&self, hasher: &mut impl Hasher) {
+        hash_floats(&self.translation.to_array(), hasher);
+        hash_floats(&self.rotation.to_array(), hasher);
+        // skip scale as it's not used for gameplay
+    }
+}
 
-    transform.rotation.x.to_bits().hash(&mut hasher);
-    transform.rotation.y.to_bits().hash(&mut hasher);
-    transform.rotation.z.to_bits().hash(&mut hasher);
-    transform.rotation.w.to_bits().hash(&mut hasher);
+impl StableHash for Player {
+    fn stable_hash(&self, hasher: &mut impl Hasher) {
+        (self.handle as u64).hash(hasher);
+    }
+}
 
-    // skip transform.scale as it's not used for gameplay
+impl StableHash for MoveDir {
+    fn stable_hash(&self, hasher: &mut impl Hasher) {
+        hash_floats(&self.0.to_array(), hasher);
+    }
+}
 
-    hasher.finish()
+impl StableHash for EntityTTL {
+    fn stable_hash(&self, hasher: &mut impl Hasher) {
+        self.0.hash(hasher);
+    }
 }
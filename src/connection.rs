@@ -0,0 +1,147 @@
+use crate::{
+    ChatInput, Config, DesyncDetected, GameState, Lobby, PeerRoles, PeerRtts, RoomFromLobby,
+    Settings,
+};
+use bevy::{prelude::*, utils::HashMap};
+use bevy_ggrs::prelude::*;
+use bevy_matchbox::prelude::*;
+use std::time::Duration;
+
+/// Peers we haven't heard from lately, and how long until GGRS gives up on them.
+#[derive(Resource, Default)]
+pub struct Interruptions(HashMap<PeerId, Timer>);
+
+#[derive(Component)]
+pub struct InterruptionText;
+
+pub fn handle_ggrs_events(
+    mut session: ResMut<Session<Config>>,
+    mut interruptions: ResMut<Interruptions>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut desyncs: EventWriter<DesyncDetected>,
+) {
+    let events: Vec<_> = match session.as_mut() {
+        Session::P2P(s) => s.events().collect(),
+        Session::Spectator(s) => s.events().collect(),
+        Session::SyncTest(_) => return,
+    };
+    for event in events {
+        match event {
+            GgrsEvent::NetworkInterrupted {
+                addr,
+                disconnect_timeout,
+            } => {
+                warn!("Connection to {addr} interrupted, dropping it in {disconnect_timeout}ms");
+                let timeout = Duration::from_millis(disconnect_timeout as u64);
+                interruptions
+                    .0
+                    .insert(addr, Timer::new(timeout, TimerMode::Once));
+            }
+            GgrsEvent::NetworkResumed { addr } => {
+                info!("Connection to {addr} resumed");
+                interruptions.0.remove(&addr);
+            }
+            GgrsEvent::Disconnected { addr } => {
+                let player = match session.as_ref() {
+                    Session::P2P(s) => s
+                        .handles_by_address(addr)
+                        .iter()
+                        .any(|&handle| handle < s.num_players()),
+                    // a spectator only hears from the host it watches
+                    _ => true,
+                };
+                if player {
+                    // Carrying on would mean simulating against predicted inputs forever.
+                    error!("{addr} disconnected, ending the match");
+                    next_state.set(GameState::Matchmaking);
+                } else {
+                    // GGRS never waits on a spectator's inputs, so the match goes on without it.
+                    info!("Spectator {addr} disconnected");
+                    interruptions.0.remove(&addr);
+                }
+            }
+            GgrsEvent::DesyncDetected {
+                frame,
+                local_checksum,
+                remote_checksum,
+                addr,
+            } => desyncs.send(DesyncDetected {
+                frame,
+                local_checksum,
+                remote_checksum,
+                peer: addr,
+            }),
+            _ => {
+                debug!("GgrsEvent::{event:?}");
+            }
+        }
+    }
+}
+
+pub fn show_interruptions(
+    mut commands: Commands,
+    mut interruptions: ResMut<Interruptions>,
+    mut texts: Query<(Entity, &mut Text), With<InterruptionText>>,
+    time: Res<Time>,
+) {
+    if interruptions.0.is_empty() {
+        texts.for_each(|(text, _)| commands.entity(text).despawn_recursive());
+        return;
+    }
+
+    let mut lines = Vec::new();
+    for (peer, timer) in &mut interruptions.0 {
+        timer.tick(time.delta());
+        lines.push(format!(
+            "Connection to {peer} interrupted, dropping it in {:.1}s",
+            timer.remaining_secs()
+        ));
+    }
+    lines.sort();
+    let value = lines.join("\n");
+
+    if let Ok((_, mut text)) = texts.get_single_mut() {
+        text.sections[0].value = value;
+    } else {
+        commands.spawn((
+            InterruptionText,
+            TextBundle::from_section(
+                value,
+                TextStyle {
+                    font_size: 24.,
+                    color: Color::ORANGE,
+                    ..default()
+                },
+            )
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                top: Val::Px(12.),
+                left: Val::Px(12.),
+                ..default()
+            }),
+        ));
+    }
+}
+
+/// Drops the session and the socket, so that going back to [`GameState::Matchmaking`] starts
+/// over in the lobby of the room given up front, or asks for a room again.
+pub fn end_match(
+    mut commands: Commands,
+    mut settings: ResMut<Settings>,
+    room_from_lobby: Option<Res<RoomFromLobby>>,
+    texts: Query<Entity, With<InterruptionText>>,
+) {
+    if room_from_lobby.is_some() {
+        settings.room = None;
+        commands.remove_resource::<RoomFromLobby>();
+    }
+    commands.remove_resource::<Session<Config>>();
+    commands.close_socket::<MultipleChannels>();
+    commands.remove_resource::<PeerRoles>();
+    commands.remove_resource::<PeerRtts>();
+    commands.remove_resource::<Lobby>();
+    commands.insert_resource(Interruptions::default());
+    // nobody is left to read a message typed now
+    commands.insert_resource(ChatInput::default());
+    texts.for_each(|text| commands.entity(text).despawn_recursive());
+}
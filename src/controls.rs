@@ -0,0 +1,201 @@
+use crate::{axis, ChatInput, Config, Mode, Settings, ShipInput, AXIS_MAX, FIRE};
+use bevy::{ecs::system::SystemParam, prelude::*, utils::HashMap};
+use bevy_ggrs::{LocalInputs, LocalPlayers};
+
+/// The keys for one player. Online, or with a single local handle, both of the first two work.
+pub struct KeyMap {
+    pitch_up: KeyCode,
+    pitch_down: KeyCode,
+    yaw_left: KeyCode,
+    yaw_right: KeyCode,
+    roll_left: KeyCode,
+    roll_right: KeyCode,
+    fire: KeyCode,
+}
+
+/// One per local handle when playing split-screen, which is as many as can share a keyboard.
+pub const KEY_MAPS: [KeyMap; 4] = [
+    KeyMap {
+        pitch_up: KeyCode::W,
+        pitch_down: KeyCode::S,
+        yaw_left: KeyCode::A,
+        yaw_right: KeyCode::D,
+        roll_left: KeyCode::Z,
+        roll_right: KeyCode::X,
+        fire: KeyCode::Space,
+    },
+    KeyMap {
+        pitch_up: KeyCode::Up,
+        pitch_down: KeyCode::Down,
+        yaw_left: KeyCode::Left,
+        yaw_right: KeyCode::Right,
+        roll_left: KeyCode::Comma,
+        roll_right: KeyCode::Period,
+        fire: KeyCode::Return,
+    },
+    KeyMap {
+        pitch_up: KeyCode::I,
+        pitch_down: KeyCode::K,
+        yaw_left: KeyCode::J,
+        yaw_right: KeyCode::L,
+        roll_left: KeyCode::U,
+        roll_right: KeyCode::O,
+        fire: KeyCode::B,
+    },
+    KeyMap {
+        pitch_up: KeyCode::Numpad8,
+        pitch_down: KeyCode::Numpad5,
+        yaw_left: KeyCode::Numpad4,
+        yaw_right: KeyCode::Numpad6,
+        roll_left: KeyCode::Numpad7,
+        roll_right: KeyCode::Numpad9,
+        fire: KeyCode::Numpad0,
+    },
+];
+
+impl KeyMap {
+    fn input(&self, keys: &Input<KeyCode>) -> ShipInput {
+        let held = |positive, negative| {
+            AXIS_MAX * keys.pressed(positive) as i8 - AXIS_MAX * keys.pressed(negative) as i8
+        };
+        ShipInput {
+            pitch: held(self.pitch_up, self.pitch_down),
+            yaw: held(self.yaw_left, self.yaw_right),
+            roll: held(self.roll_left, self.roll_right),
+            throttle: 0,
+            buttons: if keys.just_pressed(self.fire) {
+                FIRE
+            } else {
+                0
+            },
+        }
+    }
+}
+
+/// The keyboard and gamepads of this computer. The keyboard is ignored while typing in the
+/// chat.
+#[derive(SystemParam)]
+pub struct Controls<'w> {
+    keys: Res<'w, Input<KeyCode>>,
+    chat: Res<'w, ChatInput>,
+    gamepads: Res<'w, Gamepads>,
+    sticks: Res<'w, Axis<GamepadAxis>>,
+    triggers: Res<'w, Axis<GamepadButton>>,
+    buttons: Res<'w, Input<GamepadButton>>,
+}
+
+impl Controls<'_> {
+    fn key_map_input(&self, key_map: &KeyMap) -> ShipInput {
+        if self.chat.is_typing() {
+            return ShipInput::default();
+        }
+        key_map.input(&self.keys)
+    }
+
+    /// Either of the first two key maps and every gamepad, for when there is only one player at
+    /// this computer.
+    pub fn input(&self) -> ShipInput {
+        let keys = KEY_MAPS[..2]
+            .iter()
+            .map(|key_map| self.key_map_input(key_map));
+        let gamepads = self
+            .gamepads
+            .iter()
+            .map(|gamepad| self.gamepad_input(gamepad));
+        keys.chain(gamepads)
+            .fold(ShipInput::default(), ShipInput::combine)
+    }
+
+    /// The key map and gamepad of one split-screen player. Gamepads are handed out in the order
+    /// they were connected.
+    pub fn split_screen_input(&self, index: usize) -> ShipInput {
+        let input = self.key_map_input(&KEY_MAPS[index % KEY_MAPS.len()]);
+        let mut gamepads: Vec<_> = self.gamepads.iter().collect();
+        gamepads.sort_by_key(|gamepad| gamepad.id);
+        match gamepads.get(index) {
+            Some(&gamepad) => input.combine(self.gamepad_input(gamepad)),
+            None => input,
+        }
+    }
+
+    /// The left stick pitches and yaws, the right stick rolls and the right trigger is the
+    /// throttle.
+    fn gamepad_input(&self, gamepad: Gamepad) -> ShipInput {
+        let stick = |axis_type| {
+            let value = self.sticks.get(GamepadAxis::new(gamepad, axis_type));
+            axis(value.unwrap_or_default())
+        };
+        let trigger = self
+            .triggers
+            .get(GamepadButton::new(
+                gamepad,
+                GamepadButtonType::RightTrigger2,
+            ))
+            .unwrap_or_default();
+        let fire = GamepadButton::new(gamepad, GamepadButtonType::South);
+        ShipInput {
+            pitch: stick(GamepadAxisType::LeftStickY),
+            yaw: -stick(GamepadAxisType::LeftStickX),
+            roll: -stick(GamepadAxisType::RightStickX),
+            throttle: (trigger.clamp(0., 1.) * u8::MAX as f32).round() as u8,
+            buttons: if self.buttons.just_pressed(fire) {
+                FIRE
+            } else {
+                0
+            },
+        }
+    }
+}
+
+pub fn read_local_inputs(
+    mut commands: Commands,
+    controls: Controls,
+    local_players: Res<LocalPlayers>,
+    settings: Res<Settings>,
+) {
+    let mut local_inputs = HashMap::new();
+
+    for &handle in &local_players.0 {
+        let input = if settings.mode == Mode::Local {
+            controls.split_screen_input(handle)
+        } else {
+            controls.input()
+        };
+        local_inputs.insert(handle, input);
+    }
+    commands.insert_resource(LocalInputs::<Config>(local_inputs));
+}
+
+/// Noise for every local handle, to exercise rollbacks in a SyncTest session. Always seeded the
+/// same, so a run can be repeated.
+pub fn read_random_inputs(
+    mut commands: Commands,
+    local_players: Res<LocalPlayers>,
+    mut state: Local<u64>,
+) {
+    if *state == 0 {
+        *state = 0x9E37_79B9_7F4A_7C15;
+    }
+    let mut local_inputs = HashMap::new();
+
+    for handle in &local_players.0 {
+        // xorshift64*
+        *state ^= *state >> 12;
+        *state ^= *state << 25;
+        *state ^= *state >> 27;
+        let random = state.wrapping_mul(0x2545_F491_4F6C_DD1D);
+
+        let [pitch, yaw, roll, throttle, fire, ..] = random.to_be_bytes();
+        let input = ShipInput {
+            pitch: (pitch as i8).max(-AXIS_MAX),
+            yaw: (yaw as i8).max(-AXIS_MAX),
+            roll: (roll as i8).max(-AXIS_MAX),
+            throttle,
+            // fire on one frame in eight, any more buries everything in projectiles
+            buttons: if fire & 0b111 == 0 { FIRE } else { 0 },
+        };
+
+        local_inputs.insert(*handle, input);
+    }
+    commands.insert_resource(LocalInputs::<Config>(local_inputs));
+}
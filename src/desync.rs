@@ -0,0 +1,213 @@
+//! When GGRS finds that a peer's checksum for a frame differs from ours, dump what we had for
+//! that frame so the two sides can be compared with `dev/desync_diff`.
+//!
+//! A report is plain text, one value per line:
+//!
+//! ```text
+//! peer <our peer id>
+//! remote <their peer id>
+//! frame <frame>
+//! checksum <ours> <theirs>
+//! resource <Resource>.<field> <value...>
+//! entity <rollback index> <Component>.<field> <value...>
+//! ```
+//!
+//! Entities are identified by their [`RollbackOrdered`] index, which is the same on every peer
+//! as long as they spawned the same things in the same order. An entity with a Rapier body or
+//! collider also gets its state in the simulation, as `RigidBody.<field>` and
+//! `Collider.<field>`. Floats use `{:?}`, which round-trips, so any difference in bits shows up
+//! as a difference in text.
+//!
+//! Building a report on every save adds to the cost of each resimulated frame, so reports are
+//! only kept when [`Settings::desync_reports`] asks for them.
+
+use crate::{EntityTTL, MoveDir, Player, Rng, Settings};
+use bevy::prelude::*;
+use bevy_ggrs::{Rollback, RollbackFrameCount, RollbackOrdered};
+use bevy_matchbox::prelude::*;
+use bevy_rapier3d::prelude::*;
+use std::{collections::BTreeMap, fmt::Write};
+
+/// How many frames of reports to keep. A desync is only detected once the frame is confirmed,
+/// so this needs to cover the prediction window plus however long the checksums take to arrive.
+const REPORT_FRAMES: i32 = 128;
+
+/// The state of the rollback entities as of the last time each frame was saved.
+#[derive(Resource, Default)]
+pub struct FrameReports {
+    reports: BTreeMap<i32, String>,
+    /// Once out of sync, every following frame is too. Only the first one is interesting.
+    written: bool,
+}
+
+#[derive(Event)]
+pub struct DesyncDetected {
+    pub frame: i32,
+    pub local_checksum: u128,
+    pub remote_checksum: u128,
+    pub peer: PeerId,
+}
+
+/// Everything that goes into a report, for the entities that have it.
+type ReportedComponents = (
+    &'static Rollback,
+    Option<&'static Transform>,
+    Option<&'static Player>,
+    Option<&'static MoveDir>,
+    Option<&'static EntityTTL>,
+    Option<&'static RapierRigidBodyHandle>,
+    Option<&'static RapierColliderHandle>,
+);
+
+pub fn reset_frame_reports(mut commands: Commands, settings: Res<Settings>) {
+    if settings.desync_reports {
+        commands.insert_resource(FrameReports::default());
+    } else {
+        commands.remove_resource::<FrameReports>();
+    }
+}
+
+/// Writes a line of floats, such as the coordinates of one of Rapier's vectors.
+fn write_floats(report: &mut String, prefix: &str, floats: &[f32]) {
+    report.push_str(prefix);
+    for float in floats {
+        write!(report, " {float:?}").unwrap();
+    }
+    report.push('\n');
+}
+
+pub fn record_frame_report(
+    frame: Res<RollbackFrameCount>,
+    order: Res<RollbackOrdered>,
+    rng: Res<Rng>,
+    context: Res<RapierContext>,
+    entities: Query<ReportedComponents>,
+    mut reports: ResMut<FrameReports>,
+) {
+    let frame = i32::from(*frame);
+    let mut entities: Vec<_> = entities
+        .iter()
+        .map(
+            |(&rollback, transform, player, move_dir, ttl, body, collider)| {
+                let index = order.order(rollback);
+                (index, transform, player, move_dir, ttl, body, collider)
+            },
+        )
+        .collect();
+    entities.sort_by_key(|&(index, ..)| index);
+
+    let mut report = String::new();
+    writeln!(report, "resource Rng.seed {}", rng.seed()).unwrap();
+    writeln!(report, "resource Rng.state {}", rng.state()).unwrap();
+    for (index, transform, player, move_dir, ttl, body, collider) in entities {
+        if let Some(transform) = transform {
+            let Vec3 { x, y, z } = transform.translation;
+            writeln!(
+                report,
+                "entity {index} Transform.translation {x:?} {y:?} {z:?}"
+            )
+            .unwrap();
+            let [x, y, z, w] = transform.rotation.to_array();
+            writeln!(
+                report,
+                "entity {index} Transform.rotation {x:?} {y:?} {z:?} {w:?}"
+            )
+            .unwrap();
+            let Vec3 { x, y, z } = transform.scale;
+            writeln!(report, "entity {index} Transform.scale {x:?} {y:?} {z:?}").unwrap();
+        }
+        if let Some(player) = player {
+            writeln!(report, "entity {index} Player.handle {}", player.handle).unwrap();
+        }
+        if let Some(MoveDir(Vec3 { x, y, z })) = move_dir {
+            writeln!(report, "entity {index} MoveDir.0 {x:?} {y:?} {z:?}").unwrap();
+        }
+        if let Some(EntityTTL(frames)) = ttl {
+            writeln!(report, "entity {index} EntityTTL.0 {frames}").unwrap();
+        }
+        if let Some(body) = body.and_then(|handle| context.bodies.get(handle.0)) {
+            let prefix = format!("entity {index} RigidBody");
+            let translation = body.translation().as_slice();
+            write_floats(&mut report, &format!("{prefix}.translation"), translation);
+            let rotation = body.rotation().coords.as_slice();
+            write_floats(&mut report, &format!("{prefix}.rotation"), rotation);
+            write_floats(
+                &mut report,
+                &format!("{prefix}.linvel"),
+                body.linvel().as_slice(),
+            );
+            write_floats(
+                &mut report,
+                &format!("{prefix}.angvel"),
+                body.angvel().as_slice(),
+            );
+        }
+        if let Some(collider) = collider.and_then(|handle| context.colliders.get(handle.0)) {
+            let prefix = format!("entity {index} Collider");
+            let position = collider.position();
+            let translation = position.translation.vector.as_slice();
+            write_floats(&mut report, &format!("{prefix}.translation"), translation);
+            let rotation = position.rotation.coords.as_slice();
+            write_floats(&mut report, &format!("{prefix}.rotation"), rotation);
+            let enabled = collider.is_enabled();
+            writeln!(report, "{prefix}.enabled {enabled}").unwrap();
+        }
+    }
+    reports.reports.insert(frame, report);
+
+    while let Some((&oldest, _)) = reports.reports.first_key_value() {
+        if oldest > frame - REPORT_FRAMES {
+            break;
+        }
+        reports.reports.remove(&oldest);
+    }
+}
+
+pub fn write_desync_reports(
+    mut desyncs: EventReader<DesyncDetected>,
+    mut reports: Option<ResMut<FrameReports>>,
+    mut socket: Option<ResMut<MatchboxSocket<MultipleChannels>>>,
+) {
+    for desync in desyncs.read() {
+        let frame = desync.frame;
+        let peer = desync.peer;
+        error!(
+            "Desync in frame {frame} with {peer}: our checksum is {:032X}, theirs {:032X}",
+            desync.local_checksum, desync.remote_checksum
+        );
+        let Some(reports) = reports.as_mut() else {
+            info!(
+                "Run with --desync-reports or ?desync_reports=true to write the state of the frame"
+            );
+            continue;
+        };
+        if reports.written {
+            continue;
+        }
+        let Some(entities) = reports.reports.get(&frame) else {
+            error!("No report for frame {frame}, it's more than {REPORT_FRAMES} frames old");
+            continue;
+        };
+
+        let us = socket
+            .as_mut()
+            .and_then(|socket| socket.id())
+            .map_or("local".to_string(), |id| id.to_string());
+        let report = format!(
+            "peer {us}\nremote {peer}\nframe {frame}\nchecksum {:032X} {:032X}\n{entities}",
+            desync.local_checksum, desync.remote_checksum
+        );
+        reports.written = true;
+
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            let path = format!("desync_{frame}_{us}.txt");
+            match std::fs::write(&path, report) {
+                Ok(()) => error!("Wrote the state of frame {frame} to {path}"),
+                Err(err) => error!("Failed to write {path}: {err}"),
+            }
+        }
+        #[cfg(target_arch = "wasm32")]
+        error!("State of frame {frame}:\n{report}");
+    }
+}
@@ -0,0 +1,108 @@
+//! Cosmetic effects, such as explosions, that the simulation asks for but doesn't roll back.
+//!
+//! A request is keyed by the frame it was made in and the rollback entity that caused it. When
+//! a frame is resimulated, a request made again keeps the effect already showing instead of
+//! starting a second one. When a rollback goes back past a request and the resimulation doesn't
+//! make it again, its effect is cancelled.
+
+use crate::{Explosion, MAX_PREDICTION};
+use bevy::{prelude::*, utils::HashMap};
+use bevy_ggrs::{Rollback, RollbackFrameCount};
+
+#[derive(Clone, Copy)]
+pub enum EffectKind {
+    Explosion,
+}
+
+/// An effect showing from `frame`, for `lifetime` frames.
+#[derive(Component)]
+pub struct Effect {
+    pub frame: i32,
+    pub lifetime: u32,
+}
+
+struct Request {
+    effect: Entity,
+    /// Made in a frame that was rolled back, and not made again yet.
+    stale: bool,
+}
+
+/// Requests still within reach of a rollback.
+#[derive(Resource, Default)]
+pub struct Effects {
+    requests: HashMap<(i32, Rollback), Request>,
+}
+
+impl Effects {
+    /// Shows `kind` at `transform` for `lifetime` frames, unless `source` already asked for it in
+    /// `frame`. Only for systems in `GgrsSchedule`.
+    pub fn request(
+        &mut self,
+        commands: &mut Commands,
+        frame: RollbackFrameCount,
+        source: Rollback,
+        kind: EffectKind,
+        transform: Transform,
+        lifetime: u32,
+    ) {
+        let frame = i32::from(frame);
+        if let Some(request) = self.requests.get_mut(&(frame, source)) {
+            request.stale = false;
+            return;
+        }
+        let mut effect = commands.spawn((
+            Effect { frame, lifetime },
+            SpatialBundle::from_transform(transform),
+        ));
+        match kind {
+            EffectKind::Explosion => effect.insert(Explosion),
+        };
+        let effect = effect.id();
+        self.requests.insert(
+            (frame, source),
+            Request {
+                effect,
+                stale: false,
+            },
+        );
+    }
+}
+
+pub fn reset_effects(mut commands: Commands) {
+    commands.insert_resource(Effects::default());
+}
+
+/// Runs when loading, once `RollbackFrameCount` has been set to the frame rolled back to.
+pub fn mark_stale_effects(frame: Res<RollbackFrameCount>, mut effects: ResMut<Effects>) {
+    let frame = i32::from(*frame);
+    for (&(requested, _), request) in &mut effects.requests {
+        if requested > frame {
+            request.stale = true;
+        }
+    }
+}
+
+/// Runs after GGRS is done rolling back and resimulating for this update. Cancels the effects
+/// the resimulation didn't ask for again, ends those that have run their course, and forgets
+/// the requests no rollback can reach anymore.
+pub fn settle_effects(
+    mut commands: Commands,
+    frame: Res<RollbackFrameCount>,
+    mut effects: ResMut<Effects>,
+    shown: Query<(Entity, &Effect)>,
+) {
+    let frame = i32::from(*frame);
+    let mut cancelled = Vec::new();
+    effects.requests.retain(|&(requested, _), request| {
+        if request.stale {
+            cancelled.push(request.effect);
+            return false;
+        }
+        requested >= frame - MAX_PREDICTION as i32
+    });
+    for (entity, effect) in &shown {
+        if cancelled.contains(&entity) || frame >= effect.frame + effect.lifetime as i32 {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
@@ -1,77 +1,79 @@
-use crate::Config;
-use bevy::{prelude::*, utils::HashMap};
-use bevy_ggrs::{LocalInputs, LocalPlayers};
-use std::f32::consts::TAU;
+use bevy::prelude::*;
+use bytemuck::{Pod, Zeroable};
+use std::{f32::consts::TAU, fmt, str::FromStr};
 
-const PITCH_UP: u8 = 1 << 0;
-const PITCH_DOWN: u8 = 1 << 1;
-const YAW_LEFT: u8 = 1 << 2;
-const YAW_RIGHT: u8 = 1 << 3;
-const ROLL_LEFT: u8 = 1 << 4;
-const ROLL_RIGHT: u8 = 1 << 5;
-const FIRE: u8 = 1 << 6;
+pub const FIRE: u8 = 1 << 0;
 
-pub fn read_local_inputs(
-    mut commands: Commands,
-    keys: Res<Input<KeyCode>>,
-    local_players: Res<LocalPlayers>,
-) {
-    let mut local_inputs = HashMap::new();
+/// Deflection of an axis held all the way.
+pub const AXIS_MAX: i8 = 127;
+/// Radians a ship turns in a frame with an axis held all the way.
+const MAX_TURN: f32 = TAU / 240.;
 
-    for handle in &local_players.0 {
-        let mut input = 0u8;
-
-        if keys.any_pressed([KeyCode::Up, KeyCode::W]) {
-            input |= PITCH_UP;
-        }
-        if keys.any_pressed([KeyCode::Down, KeyCode::S]) {
-            input |= PITCH_DOWN;
-        }
-        if keys.any_pressed([KeyCode::Left, KeyCode::A]) {
-            input |= YAW_LEFT
-        }
-        if keys.any_pressed([KeyCode::Right, KeyCode::D]) {
-            input |= YAW_RIGHT;
-        }
-        if keys.any_pressed([KeyCode::Comma, KeyCode::Z]) {
-            input |= ROLL_LEFT
-        }
-        if keys.any_pressed([KeyCode::Period, KeyCode::X]) {
-            input |= ROLL_RIGHT;
-        }
-        if keys.any_just_pressed([KeyCode::Space, KeyCode::Return]) {
-            input |= FIRE;
-        }
+/// One handle's input for one frame, as sent to the other peers.
+#[repr(C)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Pod, Zeroable)]
+pub struct ShipInput {
+    /// Positive pitches up, from `-AXIS_MAX` to `AXIS_MAX`.
+    pub pitch: i8,
+    /// Positive yaws left.
+    pub yaw: i8,
+    /// Positive rolls left.
+    pub roll: i8,
+    /// Zero to `u8::MAX` for full thrust.
+    pub throttle: u8,
+    /// [`FIRE`] and room for more.
+    pub buttons: u8,
+}
 
-        local_inputs.insert(*handle, input);
-    }
-    commands.insert_resource(LocalInputs::<Config>(local_inputs));
+/// Quantizes an analog axis in `-1..=1`.
+pub fn axis(value: f32) -> i8 {
+    (value.clamp(-1., 1.) * AXIS_MAX as f32).round() as i8
 }
 
-pub fn rotation(input: u8) -> Vec3 {
-    let delta = TAU / 240.;
-    let mut rotation = Vec3::ZERO;
-    if input & PITCH_UP != 0 {
-        rotation.z += delta;
+impl ShipInput {
+    /// Radians to turn about the world x (roll), y (yaw) and z (pitch) axes this frame.
+    pub fn rotation(&self) -> Vec3 {
+        Vec3::new(self.roll as f32, self.yaw as f32, self.pitch as f32) / AXIS_MAX as f32 * MAX_TURN
     }
-    if input & PITCH_DOWN != 0 {
-        rotation.z -= delta;
-    }
-    if input & YAW_RIGHT != 0 {
-        rotation.y -= delta;
-    }
-    if input & YAW_LEFT != 0 {
-        rotation.y += delta;
+
+    pub fn fire(&self) -> bool {
+        self.buttons & FIRE != 0
     }
-    if input & ROLL_RIGHT != 0 {
-        rotation.x -= delta;
+
+    /// Both inputs at once, as when a keyboard and a gamepad drive the same ship.
+    pub fn combine(self, other: Self) -> Self {
+        let add = |a: i8, b: i8| a.saturating_add(b).clamp(-AXIS_MAX, AXIS_MAX);
+        Self {
+            pitch: add(self.pitch, other.pitch),
+            yaw: add(self.yaw, other.yaw),
+            roll: add(self.roll, other.roll),
+            throttle: self.throttle.max(other.throttle),
+            buttons: self.buttons | other.buttons,
+        }
     }
-    if input & ROLL_LEFT != 0 {
-        rotation.x += delta;
+}
+
+/// The bytes of the input in hex, as in replays.
+impl fmt::Display for ShipInput {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        bytemuck::bytes_of(self)
+            .iter()
+            .try_for_each(|byte| write!(f, "{byte:02x}"))
     }
-    rotation
 }
 
-pub fn fire(input: u8) -> bool {
-    input & FIRE != 0
+impl FromStr for ShipInput {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let size = std::mem::size_of::<Self>();
+        if s.len() != 2 * size || !s.is_ascii() {
+            return Err(format!("expected {size} bytes of hex, got {s:?}"));
+        }
+        let bytes = (0..size)
+            .map(|i| u8::from_str_radix(&s[2 * i..2 * i + 2], 16))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|err| err.to_string())?;
+        Ok(bytemuck::pod_read_unaligned(&bytes))
+    }
 }
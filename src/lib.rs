@@ -0,0 +1,20 @@
+//! The deterministic part of the game: what runs in `GgrsSchedule` and what it needs, shared by
+//! the game and the headless runner.
+
+pub use components::*;
+pub use effects::*;
+pub use input::*;
+pub use physics::*;
+pub use replay_file::*;
+pub use rng::*;
+pub use rollback_check::*;
+pub use simulation::*;
+
+mod components;
+mod effects;
+mod input;
+mod physics;
+mod replay_file;
+mod rng;
+mod rollback_check;
+mod simulation;
@@ -0,0 +1,214 @@
+//! Picking a room and waiting in it. Online, [`GameState::Matchmaking`] asks for a room code,
+//! or makes one up to share, and [`GameState::Lobby`] lists who joined until every player is
+//! ready.
+
+use crate::{
+    our_role, room_members, ControlMessage, GameState, PeerRoles, PeerRtts, Role, Settings,
+    CHAT_KEY, CONTROL_CHANNEL,
+};
+use bevy::{prelude::*, utils::HashMap};
+use bevy_matchbox::prelude::*;
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hash},
+};
+
+const READY_KEY: KeyCode = KeyCode::R;
+const CODE_LENGTH: usize = 5;
+/// Letters and digits that can't be mistaken for one another when read out.
+const CODE_ALPHABET: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+
+/// Who in the room is ready, and whether the host said to start.
+#[derive(Resource, Default)]
+pub struct Lobby {
+    pub ready: HashMap<PeerId, bool>,
+    pub we_are_ready: bool,
+    pub start: bool,
+}
+
+/// The room was typed or made up in [`GameState::Matchmaking`] rather than given up front, so
+/// `end_match` forgets it and the next match asks for a room again.
+#[derive(Resource)]
+pub struct RoomFromLobby;
+
+#[derive(Component)]
+pub struct LobbyText;
+
+/// Draws the room prompt and the lobby, as the ships' cameras only come with a match.
+/// `setup_local_players` despawns it once the match starts.
+pub fn spawn_lobby_camera(mut commands: Commands, cameras: Query<(), With<Camera>>) {
+    if cameras.is_empty() {
+        commands.spawn(Camera2dBundle::default());
+    }
+}
+
+fn new_room_code(seed: impl Hash) -> String {
+    let mut random = RandomState::new().hash_one(seed);
+    (0..CODE_LENGTH)
+        .map(|_| {
+            let letter = CODE_ALPHABET[(random % CODE_ALPHABET.len() as u64) as usize];
+            random /= CODE_ALPHABET.len() as u64;
+            letter as char
+        })
+        .collect()
+}
+
+fn show_text(
+    commands: &mut Commands,
+    texts: &mut Query<&mut Text, With<LobbyText>>,
+    value: String,
+) {
+    if let Ok(mut text) = texts.get_single_mut() {
+        text.sections[0].value = value;
+    } else {
+        commands.spawn((
+            LobbyText,
+            TextBundle::from_section(
+                value,
+                TextStyle {
+                    font_size: 24.,
+                    color: Color::WHITE,
+                    ..default()
+                },
+            )
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                top: Val::Px(12.),
+                left: Val::Px(12.),
+                ..default()
+            }),
+        ));
+    }
+}
+
+/// Goes straight to the lobby when the room is known up front. Otherwise takes a code typed on
+/// the keyboard, or makes one up for an empty one.
+#[allow(clippy::too_many_arguments)]
+pub fn choose_room(
+    mut commands: Commands,
+    mut characters: EventReader<ReceivedCharacter>,
+    keys: Res<Input<KeyCode>>,
+    time: Res<Time<Real>>,
+    mut settings: ResMut<Settings>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut code: Local<String>,
+    mut texts: Query<&mut Text, With<LobbyText>>,
+) {
+    if settings.room.is_some() {
+        next_state.set(GameState::Lobby);
+        return;
+    }
+
+    for character in characters.read() {
+        let letter = character.char.to_ascii_uppercase();
+        if code.len() < CODE_LENGTH && CODE_ALPHABET.contains(&(letter as u8)) {
+            code.push(letter);
+        }
+    }
+    if keys.just_pressed(KeyCode::Back) {
+        code.pop();
+    }
+    if keys.just_pressed(KeyCode::Return) {
+        let room = if code.is_empty() {
+            // when exactly the key was pressed is as good a seed as any
+            new_room_code(time.elapsed())
+        } else {
+            std::mem::take(&mut *code)
+        };
+        info!("joining room {room}");
+        settings.room = Some(room);
+        commands.insert_resource(RoomFromLobby);
+        next_state.set(GameState::Lobby);
+        return;
+    }
+
+    let value = format!(
+        "Type a room code and press Enter to join it,\n\
+         or press Enter right away to create a new room\n\n\
+         > {}",
+        *code
+    );
+    show_text(&mut commands, &mut texts, value);
+}
+
+pub fn toggle_ready(
+    keys: Res<Input<KeyCode>>,
+    mut socket: ResMut<MatchboxSocket<MultipleChannels>>,
+    mut lobby: ResMut<Lobby>,
+    settings: Res<Settings>,
+) {
+    if !keys.just_pressed(READY_KEY) || lobby.start || our_role(&settings) != Role::Player {
+        return;
+    }
+    lobby.we_are_ready = !lobby.we_are_ready;
+    let message = ControlMessage::Ready(lobby.we_are_ready).encode();
+    let peers: Vec<_> = socket.connected_peers().collect();
+    for peer in peers {
+        socket.channel(CONTROL_CHANNEL).send(message.clone(), peer);
+    }
+}
+
+pub fn show_lobby(
+    mut commands: Commands,
+    mut socket: ResMut<MatchboxSocket<MultipleChannels>>,
+    roles: Res<PeerRoles>,
+    rtts: Res<PeerRtts>,
+    lobby: Res<Lobby>,
+    settings: Res<Settings>,
+    mut texts: Query<&mut Text, With<LobbyText>>,
+) {
+    let room = settings.room.as_deref().unwrap_or_default();
+    let mut lines = vec![
+        format!("Room {room}, share the code to play together"),
+        String::new(),
+    ];
+
+    match socket.id() {
+        None => lines.push("Connecting...".to_string()),
+        Some(our_id) => {
+            let our_role = our_role(&settings);
+            let (players, spectators) = room_members(&roles, our_id, our_role);
+            for &peer in &players {
+                let ready = if peer == our_id {
+                    lobby.we_are_ready
+                } else {
+                    lobby.ready.get(&peer).copied().unwrap_or_default()
+                };
+                let mut line = format!(
+                    "{} {}",
+                    &peer.to_string()[..8],
+                    if ready { "ready" } else { "not ready" }
+                );
+                if peer == our_id {
+                    line.push_str(" (you)");
+                } else if let Some(rtt) = rtts.median(peer) {
+                    line.push_str(&format!(" {}ms", rtt.as_millis()));
+                }
+                lines.push(line);
+            }
+            for &peer in &spectators {
+                let you = if peer == our_id { " (you)" } else { "" };
+                lines.push(format!("{} spectating{you}", &peer.to_string()[..8]));
+            }
+
+            lines.push(String::new());
+            lines.push(if lobby.start {
+                "Starting...".to_string()
+            } else if players.len() != settings.players || spectators.len() != settings.spectators {
+                format!(
+                    "Waiting for {} players and {} spectators",
+                    settings.players, settings.spectators
+                )
+            } else if our_role == Role::Player {
+                format!("{READY_KEY:?} to toggle ready, {CHAT_KEY:?} to chat")
+            } else {
+                "Waiting for the players to be ready".to_string()
+            });
+        }
+    }
+    show_text(&mut commands, &mut texts, lines.join("\n"));
+}
+
+pub fn despawn_lobby_text(mut commands: Commands, texts: Query<Entity, With<LobbyText>>) {
+    texts.for_each(|text| commands.entity(text).despawn_recursive());
+}
@@ -0,0 +1,59 @@
+use crate::{session_builder, GameState, Rng, Settings};
+use bevy::{prelude::*, render::camera::Viewport, window::PrimaryWindow};
+
+/// A camera drawing to part of the window, for handle `index` of `count` local players.
+#[derive(Component)]
+pub struct SplitScreen {
+    pub index: usize,
+    pub count: usize,
+}
+
+/// A SyncTest session that never rolls back is GGRS's way of running every handle locally.
+pub fn start_local_session(
+    mut commands: Commands,
+    settings: Res<Settings>,
+    mut next_state: ResMut<NextState<GameState>>,
+    time: Res<Time<Real>>,
+) {
+    let session = session_builder()
+        .with_num_players(settings.players)
+        .with_check_distance(0)
+        .start_synctest_session()
+        .expect("failed to start local session");
+
+    info!("started local session for {} players", settings.players);
+    // nobody else needs to agree on it
+    commands.insert_resource(Rng::new(time.elapsed().as_nanos() as u64));
+    commands.insert_resource(bevy_ggrs::Session::SyncTest(session));
+    next_state.set(GameState::InGame);
+}
+
+/// Tiles the window with as square a grid as fits every local player, keeping up with resizes.
+pub fn update_viewports(
+    windows: Query<&Window, With<PrimaryWindow>>,
+    mut cameras: Query<(&mut Camera, &SplitScreen)>,
+) {
+    let Ok(window) = windows.get_single() else {
+        return;
+    };
+    let size = UVec2::new(window.physical_width(), window.physical_height());
+    for (mut camera, &SplitScreen { index, count }) in &mut cameras {
+        let columns = (count as f32).sqrt().ceil() as u32;
+        let rows = (count as u32).div_ceil(columns);
+        let cell = size / UVec2::new(columns, rows);
+        let position = UVec2::new(index as u32 % columns, index as u32 / columns) * cell;
+        let cell = cell.max(UVec2::ONE);
+        let current = camera
+            .viewport
+            .as_ref()
+            .map(|viewport| (viewport.physical_position, viewport.physical_size));
+        // only touch the camera when the layout changes
+        if current != Some((position, cell)) {
+            camera.viewport = Some(Viewport {
+                physical_position: position,
+                physical_size: cell,
+                ..default()
+            });
+        }
+    }
+}
@@ -1,27 +1,80 @@
-use bevy::prelude::*;
-use bevy_ggrs::{ggrs::DesyncDetection, prelude::*, GgrsConfig, *};
+use bevy::{
+    core_pipeline::clear_color::ClearColorConfig, prelude::*, time::TimeSystem,
+    transform::TransformSystem,
+};
+use bevy_ggrs::{prelude::*, *};
 use bevy_matchbox::prelude::*;
 use bevy_rapier3d::prelude::*;
 use std::f32::consts::TAU;
 
-use components::*;
-use input::*;
-
-mod components;
-mod input;
-
-const PLAYER_SIZE: f32 = 1.;
-const PROJECTILE_RADIUS: f32 = 0.05;
-
-type Config = GgrsConfig<u8, PeerId>;
+use ai::*;
+use chat::*;
+use connection::*;
+use controls::*;
+use desync::*;
+use lobby::*;
+use local::*;
+use mass_networking::*;
+use matchmaking::*;
+use network_stats::*;
+use replay::*;
+use settings::*;
+use smoothing::*;
+use spawn_assets::*;
+use spectator::*;
+use sync_test::*;
+
+mod ai;
+mod chat;
+mod connection;
+mod controls;
+mod desync;
+mod lobby;
+mod local;
+mod matchmaking;
+mod network_stats;
+mod replay;
+mod settings;
+mod smoothing;
+mod spawn_assets;
+mod spectator;
+mod sync_test;
+
+// one per handle, up to `MAX_PLAYERS`; avoiding the marker and explosion colors
+const PLAYER_COLORS: [Color; MAX_PLAYERS] = [
+    Color::GOLD,
+    Color::SILVER,
+    Color::ORANGE,
+    Color::TURQUOISE,
+    Color::VIOLET,
+    Color::LIME_GREEN,
+    Color::SALMON,
+    Color::MAROON,
+];
 
 #[derive(States, Clone, Eq, PartialEq, Debug, Hash, Default)]
 enum GameState {
+    /// Starting the session, which online means picking a room first.
     #[default]
     Matchmaking,
+    /// Online, in a room until every player is ready.
+    Lobby,
     InGame,
 }
 
+/// Set before sending `AppExit` when the app can't do what it was started for, so that it exits
+/// with a failure status.
+#[derive(Resource)]
+struct ExitFailure;
+
+/// The winit runner ends the process itself once the app exits, so the status is set here.
+#[cfg(not(target_arch = "wasm32"))]
+fn exit_with_failure(mut exits: EventReader<bevy::app::AppExit>) {
+    if exits.read().next().is_some() {
+        std::process::exit(1);
+    }
+}
+
 fn main() {
     let mut app = App::new();
     #[cfg(target_arch = "wasm32")]
@@ -32,141 +85,252 @@ fn main() {
         }),
         ..default()
     }));
+    #[cfg(target_arch = "wasm32")]
+    app.insert_resource(Settings::from_query(
+        &web_sys::window()
+            .and_then(|window| window.location().search().ok())
+            .unwrap_or_default(),
+    ));
+    #[cfg(not(target_arch = "wasm32"))]
+    app.add_plugins(dev::native::SizedWindowPlugin {
+        players: MIN_PLAYERS..=MAX_PLAYERS,
+        check_distance: MIN_CHECK_DISTANCE..=MAX_CHECK_DISTANCE,
+    });
     #[cfg(not(target_arch = "wasm32"))]
-    app.add_plugins(dev::native::SizedWindowPlugin);
+    app.insert_resource(Settings::from(app.world.resource::<dev::native::Args>()));
     #[cfg(not(target_arch = "wasm32"))]
     app.add_systems(Update, dev::niceties::exits);
+    #[cfg(not(target_arch = "wasm32"))]
+    app.add_systems(
+        Last,
+        exit_with_failure.run_if(resource_exists::<ExitFailure>()),
+    );
+    #[cfg(not(target_arch = "wasm32"))]
+    app.add_systems(
+        OnEnter(GameState::Matchmaking),
+        start_signaling_server.run_if(online),
+    );
 
-    app.add_plugins(GgrsPlugin::<Config>::default())
+    app.add_plugins((GgrsPlugin::<Config>::default(), SimulationPlugin))
         .insert_resource(AmbientLight {
             brightness: 1.0,
             ..default()
         })
         // --
-        .rollback_component_with_clone::<Transform>()
-        .rollback_component_with_copy::<Player>()
-        .rollback_component_with_copy::<MoveDir>()
-        .rollback_component_with_clone::<GlobalTransform>()
-        .rollback_component_with_clone::<Visibility>()
-        .rollback_component_with_clone::<InheritedVisibility>()
-        .rollback_component_with_clone::<ViewVisibility>()
-        .checksum_component::<Transform>(checksum_transform)
+        // looks, which `add_visuals` puts back on respawned entities
+        .without_rollback::<Handle<Mesh>>()
+        .without_rollback::<Handle<StandardMaterial>>()
+        .without_rollback::<bevy::render::primitives::Aabb>()
+        // ships and their parts stay together for the whole match
+        .without_rollback::<Parent>()
+        .without_rollback::<Children>()
+        // drawing only, and kept through rollbacks to see what they changed
+        .without_rollback::<Smoothing>()
         .insert_resource(ClearColor(Color::MIDNIGHT_BLUE * 0.1))
-        .add_systems(OnEnter(GameState::Matchmaking), start_matchbox_socket)
-        .add_systems(OnEnter(GameState::InGame), setup_local_players)
+        .add_systems(
+            OnEnter(GameState::Matchmaking),
+            (
+                spawn_lobby_camera,
+                start_synctest_session.run_if(sync_test),
+                start_local_session.run_if(local.or_else(practice)),
+                start_replay_session.run_if(replaying),
+            ),
+        )
+        .add_systems(OnExit(GameState::Matchmaking), despawn_lobby_text)
+        .add_systems(OnEnter(GameState::Lobby), start_matchbox_socket)
+        .add_systems(OnExit(GameState::Lobby), despawn_lobby_text)
+        .add_systems(
+            OnEnter(GameState::InGame),
+            (
+                reset_frame_reports,
+                reset_physics,
+                reset_rollback_stats,
+                reset_effects,
+                spawn_players,
+                apply_deferred,
+                setup_local_players,
+                spawn_free_camera.run_if(spectating),
+                spawn_network_stats,
+                start_recording.run_if(recording),
+            )
+                .chain(),
+        )
+        .add_systems(
+            OnExit(GameState::InGame),
+            (
+                stop_recording.run_if(resource_exists::<ReplayRecorder>()),
+                stop_replay.run_if(resource_exists::<ReplayPlayback>()),
+                end_match,
+                despawn_match_entities,
+            )
+                .chain(),
+        )
+        .init_resource::<Interruptions>()
+        .init_resource::<ChatLog>()
+        .init_resource::<ChatInput>()
         .add_systems(
             Update,
             (
-                wait_for_players.run_if(in_state(GameState::Matchmaking)),
-                handle_ggrs_events.run_if(in_state(GameState::InGame)),
+                (
+                    handle_control_messages
+                        .run_if(resource_exists::<MatchboxSocket<MultipleChannels>>()),
+                    (receive_chat, type_chat)
+                        .chain()
+                        .run_if(resource_exists::<MatchboxSocket<MultipleChannels>>()),
+                    (
+                        update_peers,
+                        toggle_ready.run_if(not(chatting)),
+                        start_when_ready,
+                        show_lobby,
+                    )
+                        .chain()
+                        .run_if(in_state(GameState::Lobby)),
+                    show_chat,
+                )
+                    .chain(),
+                choose_room.run_if(in_state(GameState::Matchmaking).and_then(online)),
+                (handle_ggrs_events, show_interruptions, write_desync_reports)
+                    .chain()
+                    .run_if(in_state(GameState::InGame)),
+                move_free_camera.run_if(
+                    in_state(GameState::InGame)
+                        .and_then(spectating)
+                        .and_then(not(chatting)),
+                ),
+                update_viewports.run_if(in_state(GameState::InGame)),
+                (control_replay, show_replay_controls)
+                    .chain()
+                    .run_if(resource_exists::<ReplayPlayback>()),
+                (toggle_network_stats, update_network_stats)
+                    .chain()
+                    .run_if(in_state(GameState::InGame)),
+                add_visuals,
             ),
         )
-        .add_systems(ReadInputs, read_local_inputs)
-        .add_systems(Startup, spawn_players)
+        .add_systems(
+            ReadInputs,
+            (
+                read_local_inputs.run_if(
+                    not(random_inputs)
+                        .and_then(not(practice))
+                        .and_then(not(replaying)),
+                ),
+                read_practice_inputs.run_if(practice),
+                read_replay_inputs.run_if(resource_exists::<ReplayPlayback>()),
+                read_random_inputs.run_if(random_inputs),
+            ),
+        )
+        .add_systems(
+            SaveWorld,
+            (
+                check_resimulated_checksums.run_if(sync_test),
+                record_frame_report.run_if(resource_exists::<FrameReports>()),
+                record_saved_frame.run_if(resource_exists::<RollbackStats>()),
+            )
+                .after(SaveWorldSet::Snapshot),
+        )
+        .add_systems(
+            LoadWorld,
+            record_rollback.run_if(resource_exists::<RollbackStats>()),
+        )
+        .add_event::<DesyncDetected>()
         // --
         .add_state::<GameState>()
         .add_systems(
             GgrsSchedule,
-            (
-                rotate_players,
-                fire_projectile.after(rotate_players),
-                move_projectile.after(fire_projectile),
-                handle_projectile_collision.after(move_projectile),
-                kill_aged_entities.after(handle_projectile_collision),
-            ),
+            record_inputs
+                .after(SimulationSet)
+                .run_if(resource_exists::<ReplayRecorder>()),
+        )
+        .add_systems(
+            GgrsSchedule,
+            track_simulated_transforms.after(PhysicsSet::Writeback),
+        )
+        .add_systems(First, restore_simulated_transforms)
+        .add_systems(
+            PostUpdate,
+            smooth_transforms.before(TransformSystem::TransformPropagate),
+        )
+        .add_systems(
+            First,
+            step_replay
+                .after(TimeSystem)
+                .run_if(resource_exists::<ReplayPlayback>()),
+        )
+        .add_systems(
+            Last,
+            stop_recording_on_exit.run_if(resource_exists::<ReplayRecorder>()),
         )
+        .init_resource::<SpawnAssets>()
         .add_systems(Startup, spawn_reference_markers)
-        .insert_resource(RapierConfiguration {
-            gravity: Vec3::ZERO,
-            ..Default::default()
-        })
-        .add_plugins(RapierPhysicsPlugin::<NoUserData>::default())
         .run();
 }
 
 fn setup_local_players(
     mut commands: Commands,
     cameras: Query<Entity, With<Camera>>,
-    local_players: Res<LocalPlayers>,
+    session: Res<Session<Config>>,
+    settings: Res<Settings>,
     players: Query<(Entity, &Player)>,
 ) {
     cameras.for_each(|camera| commands.entity(camera).despawn_recursive());
+    // `LocalPlayers` is only updated once the session advances, so ask the session. In a
+    // SyncTest session every handle is local; follow the first, unless playing split-screen.
+    let local_players = match session.as_ref() {
+        Session::SyncTest(s) if settings.mode == Mode::Local => (0..s.num_players()).collect(),
+        Session::SyncTest(_) => vec![0],
+        Session::P2P(s) => s.local_player_handles(),
+        Session::Spectator(_) => vec![],
+    };
     for (player, &Player { handle }) in &players {
         let transform = Transform::from_translation(Vec3::new(0., 3.4, 4.0))
             .with_rotation(Quat::from_rotation_x(TAU * -0.049));
-        for &local_player in &local_players.0 {
+        let count = local_players.len();
+        for (index, &local_player) in local_players.iter().enumerate() {
             if local_player == handle {
                 commands.entity(player).with_children(|child| {
-                    child.spawn(Camera3dBundle {
+                    let mut camera = child.spawn(Camera3dBundle {
+                        camera: Camera {
+                            order: index as isize,
+                            ..default()
+                        },
+                        camera_3d: Camera3d {
+                            // the first view clears the window, the others would clear the
+                            // views drawn before them
+                            clear_color: if index == 0 {
+                                ClearColorConfig::Default
+                            } else {
+                                ClearColorConfig::None
+                            },
+                            ..default()
+                        },
                         transform,
                         ..default()
                     });
-                });
-            }
-        }
-    }
-}
-
-fn handle_projectile_collision(
-    mut events: EventReader<CollisionEvent>,
-    projectiles: Query<&Transform, With<Projectile>>,
-    mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-) {
-    for event in events.read() {
-        match event {
-            &CollisionEvent::Started(a, b, _) => {
-                let (entity, transform) = match (projectiles.get(a), projectiles.get(b)) {
-                    (Err(_), Ok(transform)) => (b, *transform),
-                    (Ok(transform), Err(_)) => (a, *transform),
-                    (Err(_), Err(_)) => {
-                        warn!("Collision of two non-projectiles");
-                        return;
-                    }
-                    (Ok(_), Ok(_)) => {
-                        warn!("Collision of two projectiles");
-                        return;
+                    if count > 1 {
+                        camera.insert((
+                            SplitScreen { index, count },
+                            // draw the overlays once, in the first view
+                            UiCameraConfig {
+                                show_ui: index == 0,
+                            },
+                        ));
                     }
-                };
-                commands.entity(entity).despawn_recursive();
-                // "explosion"
-                commands.spawn((
-                    EntityTTL::new(0.2),
-                    PbrBundle {
-                        mesh: meshes.add(
-                            Mesh::try_from(shape::Icosphere {
-                                radius: PLAYER_SIZE / 5.,
-                                ..Default::default()
-                            })
-                            .unwrap(),
-                        ),
-                        material: materials.add(Color::PINK.into()),
-                        transform,
-                        ..Default::default()
-                    },
-                ));
+                });
             }
-            _ => {}
         }
     }
 }
 
 fn spawn_reference_markers(
     mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
+    assets: Res<SpawnAssets>,
     mut materials: ResMut<Assets<StandardMaterial>>,
 ) {
     // origin
     commands.spawn(PbrBundle {
-        mesh: meshes.add(
-            Mesh::try_from(shape::Icosphere {
-                radius: 0.03,
-                ..Default::default()
-            })
-            .unwrap(),
-        ),
-        material: materials.add(Color::WHITE.into()),
+        mesh: assets.origin_marker_mesh.clone(),
+        material: assets.white_material.clone(),
         ..Default::default()
     });
     for value in 1..=15 {
@@ -181,13 +345,7 @@ fn spawn_reference_markers(
                 let b = l - direction.z;
                 let color = Color::rgb(r, g, b);
                 commands.spawn(PbrBundle {
-                    mesh: meshes.add(
-                        Mesh::try_from(shape::Icosphere {
-                            radius: 0.025,
-                            ..Default::default()
-                        })
-                        .unwrap(),
-                    ),
+                    mesh: assets.axis_marker_mesh.clone(),
                     material: materials.add(color.into()),
                     transform,
                     ..Default::default()
@@ -199,10 +357,10 @@ fn spawn_reference_markers(
 
 fn spawn_players(
     mut commands: Commands,
+    session: Res<Session<Config>>,
     players: Query<Entity, With<Player>>,
     projectiles: Query<Entity, With<Projectile>>,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
+    assets: Res<SpawnAssets>,
 ) {
     for player in &players {
         commands.entity(player).despawn_recursive();
@@ -212,68 +370,26 @@ fn spawn_players(
         commands.entity(projectile).despawn_recursive();
     }
 
-    struct Attrs {
-        handle: usize,
-        position: Vec3,
-        color: Color,
-    }
-
-    let player_attrs = [
-        Attrs {
-            handle: 0,
-            position: Vec3::new(-2., 0., 0.),
-            color: Color::GOLD,
-        },
-        Attrs {
-            handle: 1,
-            position: Vec3::new(2., 0., 0.),
-            color: Color::SILVER,
-        },
-    ];
-
-    struct Marker {
-        position: Vec3,
-        color: Color,
-    }
+    let num_players = match session.as_ref() {
+        Session::SyncTest(s) => s.num_players(),
+        Session::P2P(s) => s.num_players(),
+        Session::Spectator(s) => s.num_players(),
+    };
 
     let marker_offset = PLAYER_SIZE / 1.8;
-    let markers = &[
-        Marker {
-            position: Vec3::X * marker_offset,
-            color: Color::RED,
-        },
-        Marker {
-            position: Vec3::Y * marker_offset,
-            color: Color::GREEN,
-        },
-        Marker {
-            position: Vec3::Z * marker_offset,
-            color: Color::BLUE,
-        },
-    ];
+    // in the order of `SpawnAssets::marker_materials`
+    let markers = [Vec3::X, Vec3::Y, Vec3::Z].map(|axis| axis * marker_offset);
 
-    for attr in player_attrs {
-        let collider_size = PLAYER_SIZE / 2.;
+    for handle in 0..num_players {
         commands
             .spawn((
-                RigidBody::Dynamic,
-                ActiveEvents::COLLISION_EVENTS,
-                Sensor::default(),
-                Collider::cuboid(collider_size, collider_size, collider_size),
-                Player {
-                    handle: attr.handle,
-                },
+                ship_body(),
+                Player { handle },
+                Smoothing::new(spawn_transform(handle, num_players)),
                 PbrBundle {
-                    mesh: meshes.add(
-                        Mesh::try_from(shape::Cube {
-                            size: PLAYER_SIZE,
-                            ..Default::default()
-                        })
-                        .unwrap(),
-                    ),
-                    material: materials.add(attr.color.into()),
-                    transform: Transform::from_translation(attr.position)
-                        .looking_at(Vec3::ZERO, Vec3::Y),
+                    mesh: assets.player_mesh.clone(),
+                    material: assets.player_materials[handle % MAX_PLAYERS].clone(),
+                    transform: spawn_transform(handle, num_players),
                     ..Default::default()
                 },
             ))
@@ -281,38 +397,23 @@ fn spawn_players(
             // so `.add_rollback()` to children manually.
             .with_children(|child| {
                 // position markers
-                for marker in markers {
+                for (position, material) in markers.into_iter().zip(&assets.marker_materials) {
                     child
                         .spawn(PbrBundle {
-                            mesh: meshes.add(
-                                Mesh::try_from(shape::Icosphere {
-                                    radius: PLAYER_SIZE / 6.8,
-                                    ..Default::default()
-                                })
-                                .unwrap(),
-                            ),
-                            material: materials.add(marker.color.into()),
-                            transform: Transform::from_translation(marker.position),
+                            mesh: assets.marker_mesh.clone(),
+                            material: material.clone(),
+                            transform: Transform::from_translation(position),
                             ..Default::default()
                         })
                         .add_rollback();
                 }
                 // barrel
-                let barrel_length = PLAYER_SIZE;
-                let barrel_radius = 0.05 * PLAYER_SIZE;
                 child
                     .spawn(PbrBundle {
-                        mesh: meshes.add(
-                            Mesh::try_from(shape::Capsule {
-                                radius: barrel_radius,
-                                depth: barrel_length,
-                                ..Default::default()
-                            })
-                            .unwrap(),
-                        ),
-                        material: materials.add(Color::WHITE.into()),
+                        mesh: assets.barrel_mesh.clone(),
+                        material: assets.white_material.clone(),
                         transform: Transform::from_rotation(Quat::from_rotation_x(TAU / 4.0))
-                            .with_translation(-Vec3::Z * barrel_length * 1.01),
+                            .with_translation(-Vec3::Z * BARREL_LENGTH * 1.01),
                         ..Default::default()
                     })
                     .add_rollback();
@@ -321,157 +422,41 @@ fn spawn_players(
     }
 }
 
-fn start_matchbox_socket(mut commands: Commands) {
-    let room_url = "ws://127.0.0.1:3536/extreme_bevy?next=2";
-    commands.insert_resource(MatchboxSocket::new_ggrs(room_url));
-    info!("started matchbox socket");
-}
-
-fn wait_for_players(
-    mut commands: Commands,
-    mut socket: ResMut<MatchboxSocket<SingleChannel>>,
-    mut next_state: ResMut<NextState<GameState>>,
-) {
-    match socket.get_channel(0) {
-        Ok(_) => {}
-        Err(err) => {
-            error!("When trying to get channel: {err:?}");
-            return;
-        }
-    }
-
-    socket.update_peers();
-    let players = socket.players();
-
-    let num_players = 2;
-    if players.len() < num_players {
-        return;
-    }
-
-    let mut session_builder = ggrs::SessionBuilder::<Config>::new()
-        .with_num_players(num_players)
-        .with_desync_detection_mode(DesyncDetection::On { interval: 1 })
-        .with_input_delay(2);
-
-    for (i, player) in players.into_iter().enumerate() {
-        session_builder = session_builder
-            .add_player(player, i)
-            .expect("failed to add player");
+/// Everything spawned for a match, down to the last explosion.
+type MatchEntityFilter = Or<(
+    With<Player>,
+    With<Projectile>,
+    With<EntityTTL>,
+    With<Effect>,
+    With<FreeCamera>,
+    With<NetworkStatsText>,
+    With<ReplayText>,
+)>;
+
+fn despawn_match_entities(mut commands: Commands, entities: Query<Entity, MatchEntityFilter>) {
+    for entity in &entities {
+        commands.entity(entity).despawn_recursive();
     }
-
-    let socket = socket.take_channel(0).unwrap();
-
-    let ggrs_session = session_builder
-        .start_p2p_session(socket)
-        .expect("failed to start session");
-
-    commands.insert_resource(bevy_ggrs::Session::P2P(ggrs_session));
-    next_state.set(GameState::InGame);
 }
 
-#[derive(Component)]
-struct EntityTTL(Timer);
-
-impl EntityTTL {
-    fn new(interval: f32) -> Self {
-        Self(Timer::from_seconds(interval, TimerMode::Repeating))
-    }
-}
-
-fn kill_aged_entities(
-    mut entities: Query<(Entity, &mut EntityTTL)>,
+/// The simulation only spawns what it needs to run, the meshes are added here. Entities that
+/// GGRS respawns when rolling back come back without them, and get them again.
+fn add_visuals(
     mut commands: Commands,
-    time: Res<Time>,
-) {
-    let delta = time.delta();
-    for (entity, mut entity_ttl) in entities.iter_mut() {
-        entity_ttl.0.tick(delta);
-        if entity_ttl.0.just_finished() {
-            commands.entity(entity).despawn_recursive();
-        }
-    }
-}
-
-fn handle_ggrs_events(mut session: ResMut<Session<Config>>) {
-    match session.as_mut() {
-        Session::P2P(s) => {
-            for event in s.events() {
-                match event {
-                    GgrsEvent::Disconnected { .. } | GgrsEvent::NetworkInterrupted { .. } => {
-                        error!("Disconnected (quitting): {event:?}");
-                    }
-                    _ => {
-                        debug!("GgrsEvent::{event:?}");
-                    }
-                }
-            }
-        }
-        _ => {}
-    }
-}
-
-fn rotate_players(
-    mut players: Query<(&mut Transform, &Player)>,
-    inputs: Res<PlayerInputs<Config>>,
+    projectiles: Query<Entity, (With<Projectile>, Without<Handle<Mesh>>)>,
+    explosions: Query<Entity, (With<Explosion>, Without<Handle<Mesh>>)>,
+    assets: Res<SpawnAssets>,
 ) {
-    for (mut transform, player) in &mut players {
-        let (input, _) = inputs[player.handle];
-        let rotation = rotation(input);
-        transform.rotate(Quat::from_euler(
-            EulerRot::XYZ,
-            rotation.x,
-            rotation.y,
-            rotation.z,
+    for projectile in &projectiles {
+        commands.entity(projectile).insert((
+            assets.projectile_mesh.clone(),
+            assets.projectile_material.clone(),
         ));
     }
-}
-
-fn fire_projectile(
-    mut commands: Commands,
-    inputs: Res<PlayerInputs<Config>>,
-    mut players: Query<(&Transform, &Player)>,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-) {
-    for (&transform, player) in &mut players {
-        let (input, _) = inputs[player.handle];
-        if fire(input) {
-            let forward = -transform.local_z();
-            let spawn_location = transform.translation + forward * PLAYER_SIZE * 1.65;
-            commands
-                .spawn((
-                    EntityTTL::new(2.),
-                    RigidBody::Dynamic,
-                    Collider::ball(PROJECTILE_RADIUS),
-                    ActiveEvents::COLLISION_EVENTS,
-                    Sensor,
-                    Projectile,
-                    MoveDir(forward),
-                    PbrBundle {
-                        mesh: meshes.add(
-                            Mesh::try_from(shape::Icosphere {
-                                radius: PROJECTILE_RADIUS,
-                                ..Default::default()
-                            })
-                            .unwrap(),
-                        ),
-                        material: materials.add(Color::RED.into()),
-                        transform: Transform::from_translation(spawn_location),
-                        ..Default::default()
-                    },
-                ))
-                .add_rollback();
-        }
-    }
-}
-
-fn move_projectile(
-    mut projectile: Query<(&mut Transform, &MoveDir), With<Projectile>>,
-    time: Res<Time>,
-) {
-    for (mut transform, dir) in &mut projectile {
-        let speed = 5.;
-        let delta = dir.0 * speed * time.delta_seconds();
-        transform.translation += delta;
+    for explosion in &explosions {
+        commands.entity(explosion).insert((
+            assets.explosion_mesh.clone(),
+            assets.explosion_material.clone(),
+        ));
     }
 }
@@ -0,0 +1,381 @@
+use crate::{session_builder, GameState, Lobby, Mode, Rng, Settings};
+use bevy::{
+    prelude::*,
+    utils::{HashMap, Instant},
+};
+use bevy_ggrs::ggrs::{DesyncDetection, PlayerType};
+use bevy_matchbox::{matchbox_socket::Packet, prelude::*};
+use std::time::Duration;
+
+/// Unreliable, handed to GGRS once the session starts.
+pub const GGRS_CHANNEL: usize = 0;
+/// Reliable, for everything the peers need to tell each other outside of GGRS.
+pub const CONTROL_CHANNEL: usize = 1;
+/// Reliable, for text chat. See [`crate::chat`].
+pub const CHAT_CHANNEL: usize = 2;
+
+/// Round trips to time with every other player before picking the input delay.
+const RTT_SAMPLES: usize = 5;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Role {
+    Player,
+    Spectator,
+}
+
+/// Messages on [`CONTROL_CHANNEL`]. Each is a tag byte followed by its payload.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ControlMessage {
+    /// Sent to every peer as soon as it connects.
+    Role(Role),
+    /// Answered with a [`ControlMessage::Pong`] carrying the same number.
+    Ping(u32),
+    Pong(u32),
+    /// Sent to every peer when a player toggles ready, and to each new peer.
+    Ready(bool),
+    /// Sent by the host once every player is ready.
+    Start,
+}
+
+impl ControlMessage {
+    pub fn encode(self) -> Packet {
+        match self {
+            Self::Role(Role::Player) => Box::new([0, 0]),
+            Self::Role(Role::Spectator) => Box::new([0, 1]),
+            Self::Ping(id) => [1].into_iter().chain(id.to_le_bytes()).collect(),
+            Self::Pong(id) => [2].into_iter().chain(id.to_le_bytes()).collect(),
+            Self::Ready(ready) => Box::new([3, ready as u8]),
+            Self::Start => Box::new([4]),
+        }
+    }
+
+    pub fn decode(packet: &[u8]) -> Option<Self> {
+        match packet {
+            [0, 0] => Some(Self::Role(Role::Player)),
+            [0, 1] => Some(Self::Role(Role::Spectator)),
+            [1, id @ ..] => Some(Self::Ping(u32::from_le_bytes(id.try_into().ok()?))),
+            [2, id @ ..] => Some(Self::Pong(u32::from_le_bytes(id.try_into().ok()?))),
+            [3, 0] => Some(Self::Ready(false)),
+            [3, 1] => Some(Self::Ready(true)),
+            [4] => Some(Self::Start),
+            _ => None,
+        }
+    }
+}
+
+/// What the connected peers told us about themselves.
+#[derive(Resource, Default)]
+pub struct PeerRoles(pub HashMap<PeerId, Role>);
+
+/// Round trip times to the connected peers, timed over [`CONTROL_CHANNEL`].
+#[derive(Resource, Default)]
+pub struct PeerRtts {
+    next_ping: u32,
+    /// The ping each peer hasn't answered yet.
+    pending: HashMap<PeerId, (u32, Instant)>,
+    samples: HashMap<PeerId, Vec<Duration>>,
+}
+
+impl PeerRtts {
+    fn ping(&mut self, socket: &mut MatchboxSocket<MultipleChannels>, peer: PeerId) {
+        let id = self.next_ping;
+        self.next_ping = self.next_ping.wrapping_add(1);
+        self.pending.insert(peer, (id, Instant::now()));
+        let message = ControlMessage::Ping(id).encode();
+        socket.channel(CONTROL_CHANNEL).send(message, peer);
+    }
+
+    pub fn median(&self, peer: PeerId) -> Option<Duration> {
+        let mut samples = self.samples.get(&peer)?.clone();
+        samples.sort();
+        samples.get(samples.len() / 2).copied()
+    }
+}
+
+/// With `--start-signaling`, starts the development signaling server in the background unless
+/// something already listens where `--signaling` points.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn start_signaling_server(args: Res<dev::native::Args>, settings: Res<Settings>) {
+    if !args.start_signaling {
+        return;
+    }
+    match dev::signaling::start_unless_running(&settings.signaling_url) {
+        Ok(true) => info!("started a signaling server for {}", settings.signaling_url),
+        Ok(false) => {}
+        Err(err) => warn!(
+            "Can't start a signaling server for {}: {err}",
+            settings.signaling_url
+        ),
+    }
+}
+
+pub fn start_matchbox_socket(mut commands: Commands, settings: Res<Settings>) {
+    let Some(room_url) = settings.room_url() else {
+        error!("No room to connect to");
+        return;
+    };
+    info!("connecting to matchbox server: {room_url}");
+    let socket = WebRtcSocketBuilder::new(room_url)
+        .add_ggrs_channel()
+        .add_reliable_channel()
+        .add_reliable_channel();
+    commands.insert_resource(MatchboxSocket::from(socket));
+    commands.insert_resource(PeerRoles::default());
+    commands.insert_resource(PeerRtts::default());
+    commands.insert_resource(Lobby::default());
+    info!("started matchbox socket");
+}
+
+/// Runs for as long as the socket is open, so peers still matchmaking get their pings answered
+/// by the ones that already started.
+pub fn handle_control_messages(
+    mut socket: ResMut<MatchboxSocket<MultipleChannels>>,
+    mut roles: ResMut<PeerRoles>,
+    mut rtts: ResMut<PeerRtts>,
+    mut lobby: ResMut<Lobby>,
+) {
+    for (peer, packet) in socket.channel(CONTROL_CHANNEL).receive() {
+        match ControlMessage::decode(&packet) {
+            Some(ControlMessage::Role(role)) => {
+                roles.0.insert(peer, role);
+            }
+            Some(ControlMessage::Ping(id)) => {
+                let message = ControlMessage::Pong(id).encode();
+                socket.channel(CONTROL_CHANNEL).send(message, peer);
+            }
+            Some(ControlMessage::Pong(id)) => match rtts.pending.get(&peer) {
+                Some(&(pending, sent)) if pending == id => {
+                    rtts.pending.remove(&peer);
+                    rtts.samples.entry(peer).or_default().push(sent.elapsed());
+                }
+                _ => warn!("Unexpected pong {id} from {peer}"),
+            },
+            Some(ControlMessage::Ready(ready)) => {
+                lobby.ready.insert(peer, ready);
+            }
+            Some(ControlMessage::Start) => lobby.start = true,
+            None => warn!("Unknown control message from {peer}: {packet:?}"),
+        }
+    }
+}
+
+/// The role we take in the room.
+pub fn our_role(settings: &Settings) -> Role {
+    match settings.mode {
+        Mode::Spectate => Role::Spectator,
+        _ => Role::Player,
+    }
+}
+
+/// Introduces us to peers joining the room, forgets the ones leaving and times the round trip to
+/// the other players.
+pub fn update_peers(
+    mut socket: ResMut<MatchboxSocket<MultipleChannels>>,
+    mut roles: ResMut<PeerRoles>,
+    mut rtts: ResMut<PeerRtts>,
+    mut lobby: ResMut<Lobby>,
+    settings: Res<Settings>,
+) {
+    match socket.get_channel(GGRS_CHANNEL) {
+        Ok(_) => {}
+        Err(err) => {
+            error!("When trying to get channel: {err:?}");
+            return;
+        }
+    }
+
+    let our_role = our_role(&settings);
+    for (peer, state) in socket.update_peers() {
+        match state {
+            PeerState::Connected => {
+                let message = ControlMessage::Role(our_role).encode();
+                socket.channel(CONTROL_CHANNEL).send(message, peer);
+                let message = ControlMessage::Ready(lobby.we_are_ready).encode();
+                socket.channel(CONTROL_CHANNEL).send(message, peer);
+            }
+            PeerState::Disconnected => {
+                roles.0.remove(&peer);
+                rtts.pending.remove(&peer);
+                rtts.samples.remove(&peer);
+                lobby.ready.remove(&peer);
+                // whoever is left has to be ready again before the host starts
+                lobby.start = false;
+            }
+        }
+    }
+
+    // Only players have inputs to delay, so only they need to know the way to the others
+    if our_role == Role::Player {
+        let players: Vec<_> = roles
+            .0
+            .iter()
+            .filter(|(_, &role)| role == Role::Player)
+            .map(|(&peer, _)| peer)
+            .collect();
+        for peer in players {
+            let samples = rtts.samples.get(&peer).map_or(0, Vec::len);
+            if samples < RTT_SAMPLES && !rtts.pending.contains_key(&peer) {
+                rtts.ping(&mut socket, peer);
+            }
+        }
+    }
+}
+
+/// The room's players and spectators, including us, each sorted the same on every peer.
+pub fn room_members(
+    roles: &PeerRoles,
+    our_id: PeerId,
+    our_role: Role,
+) -> (Vec<PeerId>, Vec<PeerId>) {
+    let members = |role| {
+        let mut peers: Vec<_> = roles
+            .0
+            .iter()
+            .filter(|(_, &peer_role)| peer_role == role)
+            .map(|(&peer, _)| peer)
+            .chain((our_role == role).then_some(our_id))
+            .collect();
+        peers.sort();
+        peers
+    };
+    (members(Role::Player), members(Role::Spectator))
+}
+
+/// Starts the session once the room has the players and spectators we expect and the host has
+/// seen every player ready. The host decides, so nobody starts while another peer un-readies.
+pub fn start_when_ready(
+    mut commands: Commands,
+    mut socket: ResMut<MatchboxSocket<MultipleChannels>>,
+    roles: Res<PeerRoles>,
+    rtts: Res<PeerRtts>,
+    mut lobby: ResMut<Lobby>,
+    mut next_state: ResMut<NextState<GameState>>,
+    settings: Res<Settings>,
+) {
+    let Some(our_id) = socket.id() else {
+        return;
+    };
+    let our_role = our_role(&settings);
+    let (players, spectators) = room_members(&roles, our_id, our_role);
+    if players.len() != settings.players || spectators.len() != settings.spectators {
+        return;
+    }
+
+    // The host also takes care of spectators once the match runs. Any player would do, so
+    // pick one everybody agrees on.
+    let host = players[0];
+    if host == our_id && !lobby.start {
+        let everyone_ready = lobby.we_are_ready
+            && players
+                .iter()
+                .filter(|&&peer| peer != our_id)
+                .all(|peer| lobby.ready.get(peer).copied().unwrap_or_default());
+        if !everyone_ready {
+            return;
+        }
+        info!("everyone is ready, starting");
+        let peers: Vec<_> = socket.connected_peers().collect();
+        for peer in peers {
+            let message = ControlMessage::Start.encode();
+            socket.channel(CONTROL_CHANNEL).send(message, peer);
+        }
+        lobby.start = true;
+    }
+    if !lobby.start {
+        return;
+    }
+
+    // Let the slowest way to another player decide the input delay.
+    let mut rtt = Duration::ZERO;
+    if our_role == Role::Player {
+        for &peer in players.iter().filter(|&&peer| peer != our_id) {
+            let samples = rtts.samples.get(&peer).map_or(0, Vec::len);
+            if samples < RTT_SAMPLES {
+                return;
+            }
+            rtt = rtt.max(rtts.median(peer).unwrap());
+        }
+    }
+    let input_delay = settings.input_delay.frames(rtt);
+    info!("input delay is {input_delay} frames, for a round trip time of {rtt:?}");
+
+    let session_builder = session_builder()
+        .with_num_players(settings.players)
+        .with_desync_detection_mode(DesyncDetection::On { interval: 1 })
+        .with_input_delay(input_delay);
+
+    let channel = socket.take_channel(GGRS_CHANNEL).unwrap();
+
+    // Spectators sync with the host, who forwards them the confirmed inputs.
+    let session = if our_role == Role::Spectator {
+        info!("spectating, host is {host}");
+        bevy_ggrs::Session::Spectator(session_builder.start_spectator_session(host, channel))
+    } else {
+        let mut session_builder = session_builder;
+        for (handle, &player) in players.iter().enumerate() {
+            let player_type = if player == our_id {
+                PlayerType::Local
+            } else {
+                PlayerType::Remote(player)
+            };
+            session_builder = session_builder
+                .add_player(player_type, handle)
+                .expect("failed to add player");
+        }
+        if our_id == host {
+            for (i, &spectator) in spectators.iter().enumerate() {
+                session_builder = session_builder
+                    .add_player(PlayerType::Spectator(spectator), settings.players + i)
+                    .expect("failed to add spectator");
+            }
+        }
+        let ggrs_session = session_builder
+            .start_p2p_session(channel)
+            .expect("failed to start session");
+        bevy_ggrs::Session::P2P(ggrs_session)
+    };
+
+    // new peer ids for every match, so a rematch draws different numbers
+    let seed = Rng::seed_from(players.iter().flat_map(|player| {
+        let id = player.0.as_u128();
+        [id as u64, (id >> 64) as u64]
+    }));
+    commands.insert_resource(Rng::new(seed));
+    commands.insert_resource(session);
+    next_state.set(GameState::InGame);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn control_messages_round_trip() {
+        for message in [
+            ControlMessage::Role(Role::Player),
+            ControlMessage::Role(Role::Spectator),
+            ControlMessage::Ping(0),
+            ControlMessage::Pong(u32::MAX),
+            ControlMessage::Ready(false),
+            ControlMessage::Ready(true),
+            ControlMessage::Start,
+        ] {
+            assert_eq!(ControlMessage::decode(&message.encode()), Some(message));
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_control_messages() {
+        for packet in [
+            &[][..],
+            &[0, 2],
+            &[1, 0, 0, 0],
+            &[2, 0, 0, 0, 0, 0],
+            &[3, 2],
+            &[4, 0],
+            &[5],
+        ] {
+            assert_eq!(ControlMessage::decode(packet), None);
+        }
+    }
+}
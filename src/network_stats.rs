@@ -0,0 +1,149 @@
+//! An overlay with what GGRS knows about the connections, to tell network trouble apart from
+//! resimulation cost. Toggled with F3.
+
+use crate::Config;
+use bevy::prelude::*;
+use bevy_ggrs::{ggrs::NetworkStats, prelude::*, ConfirmedFrameCount, RollbackFrameCount};
+use std::{collections::VecDeque, fmt::Write, time::Duration};
+
+const TOGGLE_KEY: KeyCode = KeyCode::F3;
+const RATE_WINDOW: Duration = Duration::from_secs(1);
+
+#[derive(Resource, Default)]
+pub struct RollbackStats {
+    /// The newest frame saved, which is where a rollback starts from.
+    latest_frame: i32,
+    /// When each rollback within [`RATE_WINDOW`] happened, and how many frames it went back.
+    recent: VecDeque<(Duration, i32)>,
+    max_depth: i32,
+}
+
+#[derive(Component)]
+pub struct NetworkStatsText;
+
+pub fn reset_rollback_stats(mut commands: Commands) {
+    commands.insert_resource(RollbackStats::default());
+}
+
+pub fn record_saved_frame(frame: Res<RollbackFrameCount>, mut stats: ResMut<RollbackStats>) {
+    stats.latest_frame = stats.latest_frame.max(i32::from(*frame));
+}
+
+/// Runs when loading, once `RollbackFrameCount` has been set to the frame rolled back to.
+pub fn record_rollback(
+    frame: Res<RollbackFrameCount>,
+    mut stats: ResMut<RollbackStats>,
+    time: Res<Time<Real>>,
+) {
+    let depth = stats.latest_frame - i32::from(*frame);
+    stats.recent.push_back((time.elapsed(), depth));
+    stats.max_depth = stats.max_depth.max(depth);
+}
+
+pub fn spawn_network_stats(mut commands: Commands) {
+    commands.spawn((
+        NetworkStatsText,
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font_size: 18.,
+                color: Color::WHITE,
+                ..default()
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(12.),
+            left: Val::Px(12.),
+            ..default()
+        })
+        .with_background_color(Color::BLACK.with_a(0.6)),
+        Visibility::Hidden,
+    ));
+}
+
+pub fn toggle_network_stats(
+    keys: Res<Input<KeyCode>>,
+    mut texts: Query<&mut Visibility, With<NetworkStatsText>>,
+) {
+    if !keys.just_pressed(TOGGLE_KEY) {
+        return;
+    }
+    for mut visibility in &mut texts {
+        *visibility = match *visibility {
+            Visibility::Hidden => Visibility::Inherited,
+            _ => Visibility::Hidden,
+        };
+    }
+}
+
+pub fn update_network_stats(
+    session: Res<Session<Config>>,
+    confirmed_frame: Res<ConfirmedFrameCount>,
+    frame: Res<RollbackFrameCount>,
+    mut stats: ResMut<RollbackStats>,
+    mut texts: Query<(&mut Text, &Visibility), With<NetworkStatsText>>,
+    time: Res<Time<Real>>,
+) {
+    let now = time.elapsed();
+    while let Some(&(at, _)) = stats.recent.front() {
+        if now - at < RATE_WINDOW {
+            break;
+        }
+        stats.recent.pop_front();
+    }
+
+    let Ok((mut text, visibility)) = texts.get_single_mut() else {
+        return;
+    };
+    if visibility == Visibility::Hidden {
+        return;
+    }
+
+    let mut value = String::new();
+    writeln!(
+        value,
+        "frame {}, confirmed {}",
+        i32::from(*frame),
+        i32::from(*confirmed_frame)
+    )
+    .unwrap();
+    let recent_max = stats.recent.iter().map(|&(_, depth)| depth).max();
+    writeln!(
+        value,
+        "rollbacks {}/s, max depth {} (match {})",
+        stats.recent.len(),
+        recent_max.unwrap_or(0),
+        stats.max_depth
+    )
+    .unwrap();
+
+    let connections: Vec<_> = match session.as_ref() {
+        Session::P2P(s) => s
+            .remote_player_handles()
+            .into_iter()
+            .map(|handle| (format!("handle {handle}"), s.network_stats(handle)))
+            .collect(),
+        Session::Spectator(s) => vec![("host".to_string(), s.network_stats())],
+        Session::SyncTest(_) => vec![],
+    };
+    for (name, connection) in connections {
+        match connection {
+            Ok(NetworkStats {
+                send_queue_len,
+                ping,
+                kbps_sent,
+                local_frames_behind,
+                remote_frames_behind,
+            }) => writeln!(
+                value,
+                "{name}: ping {ping}ms, send queue {send_queue_len}, {kbps_sent} kbps, \
+                 frames behind {local_frames_behind} local / {remote_frames_behind} remote"
+            ),
+            Err(err) => writeln!(value, "{name}: {err}"),
+        }
+        .unwrap();
+    }
+
+    text.sections[0].value = value.trim_end().to_string();
+}
@@ -0,0 +1,307 @@
+//! Rapier, stepped once per rollback frame in `GgrsSchedule`, after the gameplay systems. Part
+//! of [`SimulationPlugin`](crate::SimulationPlugin), so the headless runner steps it too.
+//!
+//! [`RapierContext`] isn't `Clone`, so it takes part in rollback as a serialized
+//! [`PhysicsSnapshot`]. Only the simulation state is restored, along with the transforms
+//! bevy_rapier last gave each body. The entities stored in the bodies and colliders are set to
+//! the ones holding their rolled-back handles, and Rapier's maps from entities to handles are
+//! rebuilt from them, so that despawning an entity removes its own body and no other.
+
+use crate::{checksum, hash_floats, RollbackCheckApp, SimulationSet, StableHash, FPS};
+use bevy::{ecs::event::event_update_system, prelude::*};
+use bevy_ggrs::{prelude::*, LoadWorld, SaveWorld};
+use bevy_rapier3d::{plugin::systems, prelude::*, rapier::dynamics::RigidBodyHandle};
+use std::{collections::HashMap, hash::Hasher};
+
+pub struct PhysicsPlugin;
+
+impl Plugin for PhysicsPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(RapierConfiguration {
+            gravity: Vec3::ZERO,
+            // one step per rollback frame, regardless of how long the frame took
+            timestep_mode: TimestepMode::Fixed {
+                dt: 1. / FPS as f32,
+                substeps: 1,
+            },
+            ..Default::default()
+        })
+        .add_plugins(RapierPhysicsPlugin::<NoUserData>::default().with_default_system_setup(false))
+        .checked_rollback_with_copy::<RapierRigidBodyHandle>()
+        .checked_rollback_with_copy::<RapierColliderHandle>()
+        .rollback_resource_with_clone::<PhysicsSnapshot>()
+        .init_resource::<PhysicsSnapshot>()
+        .checksum_resource::<RapierContext>(checksum)
+        .configure_sets(
+            GgrsSchedule,
+            (
+                PhysicsSet::SyncBackend,
+                PhysicsSet::StepSimulation,
+                PhysicsSet::Writeback,
+            )
+                .chain()
+                .after(SimulationSet),
+        )
+        // Rapier only sees what the gameplay systems spawned and despawned once their commands are
+        // applied, which has to happen before its own systems, or a saved frame would hold
+        // entities without their bodies and bodies without their entities.
+        .add_systems(
+            GgrsSchedule,
+            apply_deferred
+                .after(SimulationSet)
+                .before(PhysicsSet::SyncBackend),
+        )
+        // `GgrsSchedule` rejects systems with conflicting access and no order between them, which
+        // Rapier's own step and writeback sets have, so those are listed here in a fixed order.
+        .add_systems(
+            GgrsSchedule,
+            (
+                RapierPhysicsPlugin::<NoUserData>::get_systems(PhysicsSet::SyncBackend)
+                    .in_set(PhysicsSet::SyncBackend),
+                (
+                    event_update_system::<CollisionEvent>,
+                    event_update_system::<ContactForceEvent>,
+                    systems::step_simulation::<NoUserData>,
+                )
+                    .chain()
+                    .in_set(PhysicsSet::StepSimulation),
+                (
+                    systems::update_colliding_entities,
+                    systems::writeback_rigid_bodies,
+                    systems::writeback_mass_properties,
+                    event_update_system::<MassModifiedEvent>,
+                )
+                    .chain()
+                    .in_set(PhysicsSet::Writeback),
+            ),
+        )
+        .add_systems(SaveWorld, save_physics.before(SaveWorldSet::Snapshot))
+        .add_systems(LoadWorld, load_physics.after(LoadWorldSet::Mapping));
+    }
+}
+
+#[derive(Resource, Clone, Default)]
+pub struct PhysicsSnapshot {
+    context: Vec<u8>,
+    /// Not serialized by bevy_rapier, which compares them with `Transform` to tell whether the
+    /// gameplay systems moved a body.
+    last_body_transforms: HashMap<RigidBodyHandle, GlobalTransform>,
+}
+
+/// Leftovers from the last match would otherwise stay in the simulation, as their removal is
+/// only picked up while `GgrsSchedule` runs.
+pub fn reset_physics(mut commands: Commands) {
+    commands.insert_resource(RapierContext::default());
+    commands.insert_resource(PhysicsSnapshot::default());
+}
+
+/// Where each body is and how it moves, so that a difference in velocity shows up in the
+/// checksum before it shows up in a `Transform`. Bodies are hashed in handle order, which is the
+/// order they were added in and the same on every peer; their entities are not.
+impl StableHash for RapierContext {
+    fn stable_hash(&self, hasher: &mut impl Hasher) {
+        for (_, body) in self.bodies.iter() {
+            hash_floats(body.translation().as_slice(), hasher);
+            hash_floats(body.rotation().coords.as_slice(), hasher);
+            hash_floats(body.linvel().as_slice(), hasher);
+            hash_floats(body.angvel().as_slice(), hasher);
+        }
+    }
+}
+
+pub fn save_physics(context: Res<RapierContext>, mut snapshot: ResMut<PhysicsSnapshot>) {
+    snapshot.context = bincode::serialize(context.as_ref()).expect("failed to serialize physics");
+    snapshot.last_body_transforms = context.last_body_transforms().clone();
+}
+
+pub fn load_physics(
+    mut context: ResMut<RapierContext>,
+    snapshot: Res<PhysicsSnapshot>,
+    bodies: Query<(Entity, &RapierRigidBodyHandle)>,
+    colliders: Query<(Entity, &RapierColliderHandle)>,
+) {
+    if snapshot.context.is_empty() {
+        return;
+    }
+    let saved: RapierContext =
+        bincode::deserialize(&snapshot.context).expect("failed to deserialize physics");
+    let context = context.as_mut();
+    context.islands = saved.islands;
+    context.broad_phase = saved.broad_phase;
+    context.narrow_phase = saved.narrow_phase;
+    context.bodies = saved.bodies;
+    context.colliders = saved.colliders;
+    context.impulse_joints = saved.impulse_joints;
+    context.multibody_joints = saved.multibody_joints;
+    context.ccd_solver = saved.ccd_solver;
+    context.query_pipeline = saved.query_pipeline;
+    context.integration_parameters = saved.integration_parameters;
+    *context.last_body_transforms_mut() = snapshot.last_body_transforms.clone();
+
+    // The handles are rolled back with the entities that own them, so they say which entity now
+    // owns each body and collider, whether GGRS kept or respawned it. bevy_rapier keeps the
+    // owning entity's bits in `user_data`.
+    let owners: HashMap<_, _> = bodies
+        .iter()
+        .map(|(entity, handle)| (handle.0, entity))
+        .collect();
+    for (handle, body) in context.bodies.iter_mut() {
+        if let Some(entity) = owners.get(&handle) {
+            body.user_data = entity.to_bits() as u128;
+        }
+    }
+    let owners: HashMap<_, _> = colliders
+        .iter()
+        .map(|(entity, handle)| (handle.0, entity))
+        .collect();
+    for (handle, collider) in context.colliders.iter_mut() {
+        if let Some(entity) = owners.get(&handle) {
+            collider.user_data = entity.to_bits() as u128;
+        }
+    }
+
+    // Entries for entities the rollback despawned would otherwise remove whichever body or
+    // collider reuses their handle, and entities it respawned would never be removed.
+    let bodies = context
+        .bodies
+        .iter()
+        .map(|(handle, body)| (Entity::from_bits(body.user_data as u64), handle))
+        .collect();
+    *context.entity2body_mut() = bodies;
+    let colliders = context
+        .colliders
+        .iter()
+        .map(|(handle, collider)| (Entity::from_bits(collider.user_data as u64), handle))
+        .collect();
+    *context.entity2collider_mut() = colliders;
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use bevy::{prelude::*, time::TimeUpdateStrategy, utils::HashMap};
+    use bevy_ggrs::{
+        prelude::*, Checksum, LocalInputs, LocalPlayers, RollbackFrameCount, SaveWorld,
+        SaveWorldSet,
+    };
+    use bevy_rapier3d::prelude::*;
+    use std::time::Duration;
+
+    fn spawn_players(mut commands: Commands) {
+        for handle in 0..2 {
+            commands
+                .spawn((
+                    ship_body(),
+                    Player { handle },
+                    TransformBundle::from_transform(spawn_transform(handle, 2)),
+                ))
+                .add_rollback();
+        }
+    }
+
+    /// Both ships fire every few frames, so projectiles keep being spawned, resimulated and
+    /// despawned by hits, by age and by rollbacks.
+    fn fire(
+        mut commands: Commands,
+        local_players: Res<LocalPlayers>,
+        frame: Res<RollbackFrameCount>,
+    ) {
+        let buttons = if i32::from(*frame) % 7 == 0 { FIRE } else { 0 };
+        let inputs = local_players
+            .0
+            .iter()
+            .map(|&handle| {
+                let input = ShipInput {
+                    buttons,
+                    ..default()
+                };
+                (handle, input)
+            })
+            .collect();
+        commands.insert_resource(LocalInputs::<Config>(inputs));
+    }
+
+    /// SyncTest only logs a warning when resimulating a frame changes its checksum.
+    fn assert_checksums_match(
+        frame: Res<RollbackFrameCount>,
+        checksum: Res<Checksum>,
+        mut first: Local<HashMap<i32, u128>>,
+    ) {
+        let frame = i32::from(*frame);
+        let first = *first.entry(frame).or_insert(checksum.0);
+        assert_eq!(checksum.0, first, "frame {frame} resimulated differently");
+    }
+
+    /// Every entity with a body or a collider is mapped to its own, and every body and collider
+    /// is mapped from its entity. Those of entities despawned in the last frame are only removed
+    /// at the start of the next, so they may still be there, but mapped, so that they will be.
+    fn assert_maps_match(world: &mut World) {
+        let bodies: Vec<_> = world
+            .query::<(Entity, &RapierRigidBodyHandle)>()
+            .iter(world)
+            .map(|(entity, handle)| (entity, handle.0))
+            .collect();
+        let colliders: Vec<_> = world
+            .query::<(Entity, &RapierColliderHandle)>()
+            .iter(world)
+            .map(|(entity, handle)| (entity, handle.0))
+            .collect();
+        let context = world.resource::<RapierContext>();
+        for (entity, handle) in bodies {
+            assert_eq!(context.entity2body().get(&entity), Some(&handle));
+        }
+        for (handle, body) in context.bodies.iter() {
+            let entity = Entity::from_bits(body.user_data as u64);
+            assert_eq!(context.entity2body().get(&entity), Some(&handle));
+        }
+        assert_eq!(context.entity2body().len(), context.bodies.len());
+        for (entity, handle) in colliders {
+            assert_eq!(context.entity2collider().get(&entity), Some(&handle));
+        }
+        for (handle, collider) in context.colliders.iter() {
+            let entity = Entity::from_bits(collider.user_data as u64);
+            assert_eq!(context.entity2collider().get(&entity), Some(&handle));
+        }
+        assert_eq!(context.entity2collider().len(), context.colliders.len());
+    }
+
+    #[test]
+    fn synctest_keeps_bodies_with_their_entities() {
+        let session = session_builder()
+            .with_num_players(2)
+            .with_check_distance(4)
+            .start_synctest_session()
+            .unwrap();
+        let mut app = App::new();
+        app.add_plugins((
+            MinimalPlugins,
+            GgrsPlugin::<Config>::default(),
+            SimulationPlugin,
+        ))
+        .insert_resource(TimeUpdateStrategy::ManualDuration(
+            Duration::from_secs_f64(1. / FPS as f64) + Duration::from_micros(1),
+        ))
+        .add_systems(Startup, spawn_players)
+        .add_systems(ReadInputs, fire)
+        .add_systems(
+            SaveWorld,
+            assert_checksums_match.after(SaveWorldSet::Snapshot),
+        )
+        .insert_resource(Session::SyncTest(session));
+        app.finish();
+        app.cleanup();
+
+        let mut fired = 0;
+        for _ in 0..(PROJECTILE_LIFETIME as usize * 2) {
+            app.update();
+            assert_maps_match(&mut app.world);
+            fired = fired.max(
+                app.world
+                    .query_filtered::<(), With<Projectile>>()
+                    .iter(&app.world)
+                    .count(),
+            );
+        }
+        assert!(fired > 0, "no projectile was fired");
+    }
+}
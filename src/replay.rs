@@ -0,0 +1,264 @@
+//! Recording a match's confirmed inputs, and playing them back through `GgrsSchedule`. See
+//! [`Replay`] for the file format.
+
+use crate::{session_builder, Config, ExitFailure, GameState, Mode, Replay, Rng, Settings, FPS};
+use bevy::{app::AppExit, prelude::*};
+use bevy_ggrs::{prelude::*, LocalInputs, LocalPlayers, RollbackFrameCount};
+use std::{collections::BTreeMap, time::Duration};
+
+const PAUSE_KEY: KeyCode = KeyCode::Space;
+const STEP_KEY: KeyCode = KeyCode::Period;
+const SLOWER_KEY: KeyCode = KeyCode::Down;
+const FASTER_KEY: KeyCode = KeyCode::Up;
+const MIN_SPEED: f32 = 1. / 16.;
+
+/// The inputs of the match being played, as last simulated. A frame can be resimulated with
+/// corrected inputs until it is confirmed, so only confirmed frames are written out.
+#[derive(Resource)]
+pub struct ReplayRecorder(Replay);
+
+/// The replay being watched.
+#[derive(Resource)]
+pub struct ReplayPlayback {
+    replay: Replay,
+    speed: f32,
+    /// Frames to advance while paused.
+    steps: u32,
+}
+
+#[derive(Component)]
+pub struct ReplayText;
+
+/// Runs before the first frame, while the [`Rng`] still has its seed.
+pub fn start_recording(mut commands: Commands, session: Res<Session<Config>>, rng: Res<Rng>) {
+    let players = match session.as_ref() {
+        Session::SyncTest(s) => s.num_players(),
+        Session::P2P(s) => s.num_players(),
+        Session::Spectator(s) => s.num_players(),
+    };
+    commands.insert_resource(ReplayRecorder(Replay {
+        players,
+        fps: FPS,
+        seed: rng.seed(),
+        frames: BTreeMap::new(),
+    }));
+}
+
+pub fn record_inputs(
+    frame: Res<RollbackFrameCount>,
+    inputs: Res<PlayerInputs<Config>>,
+    mut recorder: ResMut<ReplayRecorder>,
+) {
+    let inputs = inputs.iter().map(|&(input, _)| input).collect();
+    recorder.0.frames.insert(i32::from(*frame), inputs);
+}
+
+fn save_recording(
+    commands: &mut Commands,
+    recorder: &mut ReplayRecorder,
+    session: Option<&Session<Config>>,
+    settings: &Settings,
+) {
+    let Some(path) = &settings.record else {
+        return;
+    };
+    // everything else only ever simulates confirmed inputs
+    if let Some(Session::P2P(s)) = session {
+        let confirmed = s.confirmed_frame();
+        recorder.0.frames.retain(|&frame, _| frame <= confirmed);
+    }
+    match recorder.0.write(path) {
+        Ok(()) => info!(
+            "Wrote {} frames of replay to {}",
+            recorder.0.frames.len(),
+            path.display()
+        ),
+        Err(err) => error!("Failed to write replay to {}: {err}", path.display()),
+    }
+    commands.remove_resource::<ReplayRecorder>();
+}
+
+/// Saves the recording when the match ends.
+pub fn stop_recording(
+    mut commands: Commands,
+    mut recorder: ResMut<ReplayRecorder>,
+    session: Option<Res<Session<Config>>>,
+    settings: Res<Settings>,
+) {
+    save_recording(&mut commands, &mut recorder, session.as_deref(), &settings);
+}
+
+/// Saves the recording when quitting in the middle of a match.
+pub fn stop_recording_on_exit(
+    mut commands: Commands,
+    mut exits: EventReader<AppExit>,
+    mut recorder: ResMut<ReplayRecorder>,
+    session: Option<Res<Session<Config>>>,
+    settings: Res<Settings>,
+) {
+    if exits.read().next().is_some() {
+        save_recording(&mut commands, &mut recorder, session.as_deref(), &settings);
+    }
+}
+
+/// Plays the replay back locally, with a session that never rolls back, like
+/// [`start_local_session`](crate::start_local_session).
+pub fn start_replay_session(
+    mut commands: Commands,
+    settings: Res<Settings>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut exit: EventWriter<AppExit>,
+) {
+    let Mode::Replay { path } = &settings.mode else {
+        return;
+    };
+    let replay = match Replay::read(path) {
+        Ok(replay) => replay,
+        Err(err) => {
+            error!("Can't play {}: {err}", path.display());
+            commands.insert_resource(ExitFailure);
+            exit.send(AppExit);
+            return;
+        }
+    };
+    if replay.fps != FPS {
+        warn!(
+            "{} was recorded at {} frames per second, playing it at {FPS}",
+            path.display(),
+            replay.fps
+        );
+    }
+
+    let session = session_builder()
+        .with_num_players(replay.players)
+        .with_check_distance(0)
+        .start_synctest_session()
+        .expect("failed to start replay session");
+
+    info!(
+        "playing {} frames of {} players from {}",
+        replay.frames.len(),
+        replay.players,
+        path.display()
+    );
+    commands.insert_resource(Rng::new(replay.seed));
+    commands.insert_resource(ReplayPlayback {
+        replay,
+        speed: 1.,
+        steps: 0,
+    });
+    commands.insert_resource(bevy_ggrs::Session::SyncTest(session));
+    next_state.set(GameState::InGame);
+}
+
+pub fn read_replay_inputs(
+    mut commands: Commands,
+    local_players: Res<LocalPlayers>,
+    frame: Res<RollbackFrameCount>,
+    playback: Res<ReplayPlayback>,
+) {
+    // the inputs read now are simulated in the next frame
+    let frame = i32::from(*frame) + 1;
+    let inputs = playback.replay.frames.get(&frame);
+    let local_inputs = local_players
+        .0
+        .iter()
+        .map(|&handle| {
+            let input = inputs.and_then(|inputs| inputs.get(handle).copied());
+            (handle, input.unwrap_or_default())
+        })
+        .collect();
+    commands.insert_resource(LocalInputs::<Config>(local_inputs));
+}
+
+pub fn control_replay(
+    keys: Res<Input<KeyCode>>,
+    mut playback: ResMut<ReplayPlayback>,
+    mut time: ResMut<Time<Virtual>>,
+    frame: Res<RollbackFrameCount>,
+) {
+    let last_frame = playback.replay.frames.keys().next_back().copied();
+    if last_frame.is_some_and(|last| i32::from(*frame) >= last) && !time.is_paused() {
+        info!("End of the replay");
+        time.pause();
+    }
+
+    if keys.just_pressed(PAUSE_KEY) {
+        if time.is_paused() {
+            time.unpause();
+        } else {
+            time.pause();
+        }
+    }
+    if keys.just_pressed(STEP_KEY) && time.is_paused() {
+        playback.steps += 1;
+    }
+    if keys.just_pressed(SLOWER_KEY) {
+        playback.speed = (playback.speed / 2.).max(MIN_SPEED);
+    }
+    if keys.just_pressed(FASTER_KEY) {
+        playback.speed = (playback.speed * 2.).min(1.);
+    }
+    time.set_relative_speed(playback.speed);
+}
+
+/// GGRS advances whenever enough [`Time`] has passed. While paused no time passes, so for a step
+/// pretend exactly one frame's worth did.
+pub fn step_replay(mut playback: ResMut<ReplayPlayback>, mut time: ResMut<Time>) {
+    if playback.steps == 0 {
+        return;
+    }
+    playback.steps -= 1;
+    // a hair over one frame, as GGRS only steps once strictly more than a frame has passed
+    time.advance_by(Duration::from_secs_f64(1. / FPS as f64) + Duration::from_micros(1));
+}
+
+pub fn show_replay_controls(
+    mut commands: Commands,
+    playback: Res<ReplayPlayback>,
+    time: Res<Time<Virtual>>,
+    frame: Res<RollbackFrameCount>,
+    mut texts: Query<&mut Text, With<ReplayText>>,
+) {
+    let last_frame = playback.replay.frames.keys().next_back().copied();
+    let value = format!(
+        "frame {} of {}, {}\n\
+         {PAUSE_KEY:?} to pause, {STEP_KEY:?} to step, {SLOWER_KEY:?}/{FASTER_KEY:?} for speed",
+        i32::from(*frame),
+        last_frame.unwrap_or_default(),
+        if time.is_paused() {
+            "paused".to_string()
+        } else {
+            format!("{}x", playback.speed)
+        }
+    );
+
+    if let Ok(mut text) = texts.get_single_mut() {
+        text.sections[0].value = value;
+    } else {
+        commands.spawn((
+            ReplayText,
+            TextBundle::from_section(
+                value,
+                TextStyle {
+                    font_size: 18.,
+                    color: Color::WHITE,
+                    ..default()
+                },
+            )
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                top: Val::Px(12.),
+                right: Val::Px(12.),
+                ..default()
+            }),
+        ));
+    }
+}
+
+/// Leaves [`Time`] running at its normal rate for whatever comes after the replay.
+pub fn stop_replay(mut commands: Commands, mut time: ResMut<Time<Virtual>>) {
+    commands.remove_resource::<ReplayPlayback>();
+    time.unpause();
+    time.set_relative_speed(1.);
+}
@@ -0,0 +1,189 @@
+//! The replay file format: a match's confirmed inputs, as plain text.
+//!
+//! ```text
+//! mass_networking replay 3
+//! players <count>
+//! fps <rollback frames per second>
+//! seed <the match's Rng seed>
+//! layout ring
+//! frame <frame> <input of handle 0> <input of handle 1> ...
+//! ```
+//!
+//! Each input is the bytes of a [`ShipInput`] in hex. The number after `replay` is the format
+//! version. `layout` names how ships are placed at the start, which for now is always
+//! [`spawn_position`](crate::spawn_position)'s ring.
+
+use crate::ShipInput;
+use std::{collections::BTreeMap, fmt::Write, fs, path::Path};
+
+const MAGIC: &str = "mass_networking replay";
+const VERSION: u32 = 3;
+const LAYOUT: &str = "ring";
+
+#[derive(Debug, Default)]
+pub struct Replay {
+    pub players: usize,
+    pub fps: usize,
+    pub seed: u64,
+    /// Every handle's input, by the frame it was simulated in.
+    pub frames: BTreeMap<i32, Vec<ShipInput>>,
+}
+
+impl Replay {
+    pub fn write(&self, path: &Path) -> std::io::Result<()> {
+        fs::write(path, self.to_text())
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = format!(
+            "{MAGIC} {VERSION}\nplayers {}\nfps {}\nseed {}\nlayout {LAYOUT}\n",
+            self.players, self.fps, self.seed
+        );
+        for (frame, inputs) in &self.frames {
+            write!(text, "frame {frame}").unwrap();
+            for input in inputs {
+                write!(text, " {input}").unwrap();
+            }
+            text.push('\n');
+        }
+        text
+    }
+
+    pub fn read(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|err| err.to_string())?;
+        Self::parse(&text)
+    }
+
+    pub fn is_replay(text: &str) -> bool {
+        text.starts_with(MAGIC)
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut lines = text.lines().enumerate();
+        match lines.next() {
+            Some((_, line)) if line == format!("{MAGIC} {VERSION}") => {}
+            Some((_, line)) if line.starts_with(MAGIC) => {
+                return Err(format!("unsupported version, expected {MAGIC} {VERSION}"));
+            }
+            _ => return Err("not a replay".to_string()),
+        }
+
+        let mut replay = Self::default();
+        for (number, line) in lines {
+            let error = |message: &str| format!("line {}: {message}", number + 1);
+            let mut words = line.split_whitespace();
+            match words.next() {
+                Some("players") => {
+                    replay.players = words
+                        .next()
+                        .and_then(|players| players.parse().ok())
+                        .ok_or_else(|| error("bad player count"))?;
+                }
+                Some("fps") => {
+                    replay.fps = words
+                        .next()
+                        .and_then(|fps| fps.parse().ok())
+                        .ok_or_else(|| error("bad frame rate"))?;
+                }
+                Some("seed") => {
+                    replay.seed = words
+                        .next()
+                        .and_then(|seed| seed.parse().ok())
+                        .ok_or_else(|| error("bad seed"))?;
+                }
+                Some("layout") if words.next() != Some(LAYOUT) => {
+                    return Err(error("unknown layout"));
+                }
+                Some("layout") => {}
+                Some("frame") => {
+                    let frame = words
+                        .next()
+                        .and_then(|frame| frame.parse().ok())
+                        .ok_or_else(|| error("bad frame number"))?;
+                    let inputs = words
+                        .map(str::parse)
+                        .collect::<Result<Vec<_>, _>>()
+                        .map_err(|err: String| error(&err))?;
+                    if inputs.len() != replay.players {
+                        return Err(error("wrong number of inputs"));
+                    }
+                    replay.frames.insert(frame, inputs);
+                }
+                Some(_) => return Err(error("unknown line")),
+                None => {}
+            }
+        }
+        Ok(replay)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FIRE;
+
+    fn replay() -> Replay {
+        let fire = ShipInput {
+            yaw: -127,
+            throttle: 255,
+            buttons: FIRE,
+            ..Default::default()
+        };
+        Replay {
+            players: 2,
+            fps: 60,
+            seed: u64::MAX,
+            frames: [
+                (1, vec![ShipInput::default(), fire]),
+                (2, vec![fire, ShipInput::default()]),
+            ]
+            .into(),
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let replay = replay();
+        let text = replay.to_text();
+        assert!(Replay::is_replay(&text));
+        let parsed = Replay::parse(&text).unwrap();
+        assert_eq!(parsed.players, replay.players);
+        assert_eq!(parsed.fps, replay.fps);
+        assert_eq!(parsed.seed, replay.seed);
+        assert_eq!(parsed.frames, replay.frames);
+    }
+
+    #[test]
+    fn documented_header_is_current() {
+        let header = format!("//! {MAGIC} {VERSION}\n");
+        assert!(include_str!("replay_file.rs").contains(&header));
+    }
+
+    #[test]
+    fn rejects_other_versions() {
+        let text = replay()
+            .to_text()
+            .replacen(&VERSION.to_string(), &(VERSION + 1).to_string(), 1);
+        assert!(Replay::parse(&text)
+            .unwrap_err()
+            .contains("unsupported version"));
+        assert_eq!(Replay::parse("players 2\n").unwrap_err(), "not a replay");
+    }
+
+    #[test]
+    fn rejects_malformed_lines() {
+        let header = format!("{MAGIC} {VERSION}\nplayers 2\n");
+        for (line, message) in [
+            ("frame 1 0000000000", "line 3: wrong number of inputs"),
+            ("frame x 0000000000 0000000000", "line 3: bad frame number"),
+            ("layout grid", "line 3: unknown layout"),
+            ("seed -1", "line 3: bad seed"),
+            ("speed 2", "line 3: unknown line"),
+        ] {
+            assert_eq!(
+                Replay::parse(&format!("{header}{line}\n")).unwrap_err(),
+                message
+            );
+        }
+    }
+}
@@ -0,0 +1,68 @@
+use crate::StableHash;
+use bevy::prelude::*;
+use std::hash::{Hash, Hasher};
+
+/// The one source of randomness for gameplay, rolled back with everything else so a resimulated
+/// frame draws the same numbers. Only systems in `GgrsSchedule` may draw from it, anything else
+/// would advance it differently on each peer.
+///
+/// Every peer seeds it the same at the start of a match. SplitMix64, so any seed, zero
+/// included, gives a good sequence.
+#[derive(Resource, Clone, Copy, Default, Debug)]
+pub struct Rng {
+    seed: u64,
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self { seed, state: seed }
+    }
+
+    /// What the match started with, for a replay to start the same.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// How far along the sequence it is, for desync reports.
+    pub fn state(&self) -> u64 {
+        self.state
+    }
+
+    /// A seed from values every peer knows, such as the sorted peer ids, mixed the same on
+    /// every platform.
+    pub fn seed_from(values: impl IntoIterator<Item = u64>) -> u64 {
+        values
+            .into_iter()
+            .fold(Self::new(0), |mut rng, value| {
+                Self::new(rng.next_u64() ^ value)
+            })
+            .next_u64()
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in `0..1`.
+    pub fn next_f32(&mut self) -> f32 {
+        // as many bits as an f32 has mantissa, so every value is equally likely
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    /// Uniform in `range`, which must not be empty.
+    pub fn range(&mut self, range: std::ops::Range<u32>) -> u32 {
+        let span = u64::from(range.end - range.start);
+        range.start + (self.next_u64() % span) as u32
+    }
+}
+
+impl StableHash for Rng {
+    fn stable_hash(&self, hasher: &mut impl Hasher) {
+        self.state.hash(hasher);
+    }
+}
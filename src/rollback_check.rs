@@ -0,0 +1,117 @@
+//! In debug builds, warns about components on `Rollback` entities that nothing rolls back. GGRS
+//! respawns a rolled-back entity with its registered components only, so anything else is lost.
+
+use bevy::{prelude::*, utils::HashSet};
+use bevy_ggrs::{prelude::*, Rollback};
+use std::any::TypeId;
+
+/// What the check knows about: the components registered through [`RollbackCheckApp`].
+#[derive(Resource, Default)]
+pub struct RollbackRegistry {
+    rolled_back: HashSet<TypeId>,
+    without_rollback: HashSet<TypeId>,
+}
+
+/// `rollback_component_with_*`, noted for the check, which bevy_ggrs keeps no list for.
+pub trait RollbackCheckApp {
+    fn checked_rollback_with_copy<T: Component + Copy>(&mut self) -> &mut Self;
+
+    fn checked_rollback_with_clone<T: Component + Clone>(&mut self) -> &mut Self;
+
+    /// Leaves `T` out of the check, for state rebuilt every frame or never read by the
+    /// simulation.
+    fn without_rollback<T: Component>(&mut self) -> &mut Self;
+}
+
+impl RollbackCheckApp for App {
+    fn checked_rollback_with_copy<T: Component + Copy>(&mut self) -> &mut Self {
+        self.rollback_component_with_copy::<T>();
+        registry(self).rolled_back.insert(TypeId::of::<T>());
+        self
+    }
+
+    fn checked_rollback_with_clone<T: Component + Clone>(&mut self) -> &mut Self {
+        self.rollback_component_with_clone::<T>();
+        registry(self).rolled_back.insert(TypeId::of::<T>());
+        self
+    }
+
+    fn without_rollback<T: Component>(&mut self) -> &mut Self {
+        registry(self).without_rollback.insert(TypeId::of::<T>());
+        self
+    }
+}
+
+fn registry(app: &mut App) -> Mut<'_, RollbackRegistry> {
+    app.world
+        .get_resource_or_insert_with(RollbackRegistry::default)
+}
+
+/// The names of the components on `entity` that are neither rolled back nor left out.
+pub fn unregistered_components(world: &World, entity: Entity) -> Vec<(TypeId, &str)> {
+    let registry = world.get_resource::<RollbackRegistry>();
+    world
+        .entity(entity)
+        .archetype()
+        .components()
+        .filter_map(|id| {
+            let info = world.components().get_info(id)?;
+            let type_id = info.type_id()?;
+            let registered = registry.is_some_and(|registry| {
+                registry.rolled_back.contains(&type_id)
+                    || registry.without_rollback.contains(&type_id)
+            });
+            (type_id != TypeId::of::<Rollback>() && !registered).then_some((type_id, info.name()))
+        })
+        .collect()
+}
+
+/// Warns once per component type, when a `Rollback` entity shows up with it.
+pub fn warn_unregistered_components(
+    world: &World,
+    entities: Query<Entity, Added<Rollback>>,
+    mut warned: Local<HashSet<TypeId>>,
+) {
+    for entity in &entities {
+        for (type_id, name) in unregistered_components(world, entity) {
+            if warned.insert(type_id) {
+                warn!(
+                    "{entity:?} is rolled back but its {name} isn't, register it with \
+                     `checked_rollback_with_*` or `without_rollback`"
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Config, Player};
+    use bevy::ecs::system::EntityCommand;
+    use bevy_ggrs::AddRollbackCommand;
+
+    #[test]
+    fn registered_components_are_not_reported() {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, GgrsPlugin::<Config>::default()))
+            .checked_rollback_with_clone::<Transform>()
+            .checked_rollback_with_copy::<Player>()
+            .without_rollback::<GlobalTransform>();
+        let entity = app
+            .world
+            .spawn((
+                Player { handle: 0 },
+                TransformBundle::default(),
+                Name::new("ship"),
+            ))
+            .id();
+        AddRollbackCommand.apply(entity, &mut app.world);
+
+        let unregistered: Vec<_> = unregistered_components(&app.world, entity)
+            .into_iter()
+            .map(|(type_id, _)| type_id)
+            .collect();
+        assert_eq!(unregistered, [TypeId::of::<Name>()]);
+    }
+}
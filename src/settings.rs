@@ -0,0 +1,336 @@
+use crate::{FPS, KEY_MAPS};
+#[cfg(target_arch = "wasm32")]
+use crate::{MAX_CHECK_DISTANCE, MIN_CHECK_DISTANCE};
+use bevy::prelude::*;
+use std::{path::PathBuf, time::Duration};
+
+pub const MIN_PLAYERS: usize = 2;
+pub const MAX_PLAYERS: usize = 8;
+
+/// How to find the other players: which signaling server, which room on it and how many
+/// players to wait for. Without a room, the lobby asks for one.
+///
+/// On native this comes from the command line (see `dev::native::Args`), on wasm from the
+/// page's query string, e.g. `?signaling=ws://example.com:3536&room=test&players=2`.
+#[derive(Resource, Clone, Debug)]
+pub struct Settings {
+    pub signaling_url: String,
+    /// The room code, as typed or created in the lobby or given up front.
+    pub room: Option<String>,
+    pub players: usize,
+    /// Spectators to wait for in the room, on top of `players`.
+    pub spectators: usize,
+    pub mode: Mode,
+    pub input_delay: InputDelay,
+    /// Where to write a replay of the match, replacing the previous match's.
+    pub record: Option<PathBuf>,
+    /// Keep a report of every saved frame, to write out the first one that desyncs.
+    pub desync_reports: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Mode {
+    /// Matchmake through the signaling server.
+    Online,
+    /// Matchmake, but only watch as one of the room's spectators.
+    Spectate,
+    /// No networking: every handle is local, each with its own keys and its own part of the
+    /// screen.
+    Local,
+    /// No networking: the first handle is at the keyboard, the others are steered by the AI.
+    Practice { difficulty: Difficulty },
+    /// No networking: play back a recorded match.
+    Replay { path: PathBuf },
+    /// No networking: every handle is local and GGRS rolls back `check_distance` frames every
+    /// frame, comparing checksums. The keyboard drives every handle unless `random_inputs`.
+    SyncTest {
+        check_distance: usize,
+        random_inputs: bool,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Difficulty {
+    Easy,
+    Normal,
+    Hard,
+}
+
+/// How many frames of input delay to pick for the round trip time measured while matchmaking.
+#[derive(Clone, Copy, Debug)]
+pub struct InputDelay {
+    pub min: usize,
+    pub max: usize,
+    pub policy: DelayPolicy,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DelayPolicy {
+    /// Delay inputs by the whole one-way trip, so remote inputs mostly arrive before they are
+    /// needed.
+    MinimizeRollbacks,
+    /// Delay inputs by half the one-way trip and roll back to hide the rest.
+    MinimizeLatency,
+}
+
+impl InputDelay {
+    pub fn frames(&self, rtt: Duration) -> usize {
+        let one_way = rtt.as_secs_f32() / 2. * FPS as f32;
+        let frames = match self.policy {
+            DelayPolicy::MinimizeRollbacks => one_way.ceil(),
+            DelayPolicy::MinimizeLatency => (one_way / 2.).floor(),
+        };
+        (frames as usize).clamp(self.min, self.max)
+    }
+}
+
+impl Default for InputDelay {
+    fn default() -> Self {
+        Self {
+            min: 0,
+            max: 8,
+            policy: DelayPolicy::MinimizeRollbacks,
+        }
+    }
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            signaling_url: "ws://127.0.0.1:3536".to_string(),
+            room: None,
+            players: 2,
+            spectators: 0,
+            mode: Mode::Online,
+            input_delay: InputDelay::default(),
+            record: None,
+            desync_reports: false,
+        }
+    }
+}
+
+impl Settings {
+    /// Everyone who joins the room meets everyone else in it, the lobby sorts out who plays.
+    pub fn room_url(&self) -> Option<String> {
+        let room = self.room.as_ref()?;
+        Some(format!(
+            "{}/{room}",
+            self.signaling_url.trim_end_matches('/')
+        ))
+    }
+
+    /// `?synctest=<check distance>[&random_inputs=true]` starts a SyncTest session instead of
+    /// matchmaking, `?spectators=1&spectate=true` watches a room with one spectator slot.
+    /// `?local=true` plays split-screen on this machine, `?practice=hard` against the AI.
+    /// `?min_input_delay=1&max_input_delay=4&delay_policy=minimize-latency` bounds the input
+    /// delay and picks how it is chosen. `?desync_reports=true` keeps the reports written on a
+    /// desync.
+    /// Unknown or malformed parameters are ignored (with a warning), leaving the default.
+    #[cfg(target_arch = "wasm32")]
+    pub fn from_query(query: &str) -> Self {
+        let mut settings = Self::default();
+        let mut synctest = None;
+        let mut random_inputs = false;
+        let mut spectate = false;
+        let mut local = false;
+        let mut practice = None;
+        for pair in query.trim_start_matches('?').split('&') {
+            let Some((key, value)) = pair.split_once('=') else {
+                continue;
+            };
+            let value = percent_decode(value);
+            match key {
+                "signaling" => settings.signaling_url = value,
+                "room" => settings.room = Some(value),
+                "players" => match value.parse::<usize>() {
+                    Ok(players) if (MIN_PLAYERS..=MAX_PLAYERS).contains(&players) => {
+                        settings.players = players
+                    }
+                    Ok(_) => {
+                        warn!("Ignoring players={value}: must be {MIN_PLAYERS} to {MAX_PLAYERS}")
+                    }
+                    Err(err) => warn!("Ignoring players={value}: {err}"),
+                },
+                "spectators" => match value.parse() {
+                    Ok(spectators) => settings.spectators = spectators,
+                    Err(err) => warn!("Ignoring spectators={value}: {err}"),
+                },
+                "spectate" => match value.parse() {
+                    Ok(value) => spectate = value,
+                    Err(err) => warn!("Ignoring spectate={value}: {err}"),
+                },
+                "local" => match value.parse() {
+                    Ok(value) => local = value,
+                    Err(err) => warn!("Ignoring local={value}: {err}"),
+                },
+                "practice" => match value.as_str() {
+                    "easy" => practice = Some(Difficulty::Easy),
+                    "normal" => practice = Some(Difficulty::Normal),
+                    "hard" => practice = Some(Difficulty::Hard),
+                    _ => warn!("Ignoring practice={value}: must be easy, normal or hard"),
+                },
+                "synctest" => match value.parse() {
+                    Ok(check_distance)
+                        if (MIN_CHECK_DISTANCE..=MAX_CHECK_DISTANCE).contains(&check_distance) =>
+                    {
+                        synctest = Some(check_distance)
+                    }
+                    Ok(_) => warn!(
+                        "Ignoring synctest={value}: must be {MIN_CHECK_DISTANCE} to {MAX_CHECK_DISTANCE}"
+                    ),
+                    Err(err) => warn!("Ignoring synctest={value}: {err}"),
+                },
+                "random_inputs" => match value.parse() {
+                    Ok(value) => random_inputs = value,
+                    Err(err) => warn!("Ignoring random_inputs={value}: {err}"),
+                },
+                "desync_reports" => match value.parse() {
+                    Ok(value) => settings.desync_reports = value,
+                    Err(err) => warn!("Ignoring desync_reports={value}: {err}"),
+                },
+                "min_input_delay" => match value.parse() {
+                    Ok(min) => settings.input_delay.min = min,
+                    Err(err) => warn!("Ignoring min_input_delay={value}: {err}"),
+                },
+                "max_input_delay" => match value.parse() {
+                    Ok(max) => settings.input_delay.max = max,
+                    Err(err) => warn!("Ignoring max_input_delay={value}: {err}"),
+                },
+                "delay_policy" => match value.as_str() {
+                    "minimize-rollbacks" => {
+                        settings.input_delay.policy = DelayPolicy::MinimizeRollbacks
+                    }
+                    "minimize-latency" => {
+                        settings.input_delay.policy = DelayPolicy::MinimizeLatency
+                    }
+                    _ => warn!(
+                        "Ignoring delay_policy={value}: must be minimize-rollbacks or minimize-latency"
+                    ),
+                },
+                _ => warn!("Ignoring unknown query parameter {key:?}"),
+            }
+        }
+        if let Some(check_distance) = synctest {
+            settings.mode = Mode::SyncTest {
+                check_distance,
+                random_inputs,
+            };
+        } else if let Some(difficulty) = practice {
+            settings.mode = Mode::Practice { difficulty };
+        } else if local {
+            settings.mode = Mode::Local;
+            settings.players = settings.players.min(KEY_MAPS.len());
+        } else if spectate {
+            settings.mode = Mode::Spectate;
+        }
+        settings.input_delay.max = settings.input_delay.max.max(settings.input_delay.min);
+        settings
+    }
+}
+
+/// Whether we go through matchmaking, as a player or a spectator.
+pub fn online(settings: Res<Settings>) -> bool {
+    matches!(settings.mode, Mode::Online | Mode::Spectate)
+}
+
+pub fn spectating(settings: Res<Settings>) -> bool {
+    settings.mode == Mode::Spectate
+}
+
+pub fn local(settings: Res<Settings>) -> bool {
+    settings.mode == Mode::Local
+}
+
+pub fn practice(settings: Res<Settings>) -> bool {
+    matches!(settings.mode, Mode::Practice { .. })
+}
+
+pub fn replaying(settings: Res<Settings>) -> bool {
+    matches!(settings.mode, Mode::Replay { .. })
+}
+
+pub fn recording(settings: Res<Settings>) -> bool {
+    settings.record.is_some() && !matches!(settings.mode, Mode::Replay { .. })
+}
+
+pub fn sync_test(settings: Res<Settings>) -> bool {
+    matches!(settings.mode, Mode::SyncTest { .. })
+}
+
+pub fn random_inputs(settings: Res<Settings>) -> bool {
+    matches!(
+        settings.mode,
+        Mode::SyncTest {
+            random_inputs: true,
+            ..
+        }
+    )
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl From<&dev::native::Args> for Settings {
+    fn from(args: &dev::native::Args) -> Self {
+        Self {
+            signaling_url: args.signaling.clone(),
+            room: args.room.clone(),
+            players: if args.local {
+                args.players.clamp(MIN_PLAYERS, KEY_MAPS.len())
+            } else {
+                args.players.clamp(MIN_PLAYERS, MAX_PLAYERS)
+            },
+            spectators: args.spectators,
+            mode: match args.synctest {
+                Some(check_distance) => Mode::SyncTest {
+                    check_distance,
+                    random_inputs: args.random_inputs,
+                },
+                None if args.local => Mode::Local,
+                None if args.replay.is_some() => Mode::Replay {
+                    path: args.replay.clone().unwrap(),
+                },
+                None => match args.practice {
+                    Some(difficulty) => Mode::Practice {
+                        difficulty: match difficulty {
+                            dev::native::Difficulty::Easy => Difficulty::Easy,
+                            dev::native::Difficulty::Normal => Difficulty::Normal,
+                            dev::native::Difficulty::Hard => Difficulty::Hard,
+                        },
+                    },
+                    None if args.spectate => Mode::Spectate,
+                    None => Mode::Online,
+                },
+            },
+            input_delay: InputDelay {
+                min: args.min_input_delay,
+                max: args.max_input_delay.max(args.min_input_delay),
+                policy: match args.delay_policy {
+                    dev::native::DelayPolicy::MinimizeRollbacks => DelayPolicy::MinimizeRollbacks,
+                    dev::native::DelayPolicy::MinimizeLatency => DelayPolicy::MinimizeLatency,
+                },
+            },
+            record: args.record.clone(),
+            desync_reports: args.desync_reports,
+        }
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn percent_decode(s: &str) -> String {
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut rest = s.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        let decoded = match byte {
+            b'+' => Some((b' ', tail)),
+            b'%' => tail
+                .get(..2)
+                .and_then(|hex| std::str::from_utf8(hex).ok())
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                .map(|byte| (byte, &tail[2..])),
+            _ => None,
+        };
+        let (byte, tail) = decoded.unwrap_or((byte, tail));
+        bytes.push(byte);
+        rest = tail;
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
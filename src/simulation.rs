@@ -0,0 +1,209 @@
+use crate::{
+    checksum, mark_stale_effects, settle_effects, EffectKind, Effects, EntityTTL, MoveDir,
+    PhysicsPlugin, Player, Projectile, Rng, RollbackCheckApp, ShipInput,
+};
+use bevy::prelude::*;
+use bevy_ggrs::{ggrs, prelude::*, GgrsConfig, LoadWorld, RollbackFrameCount};
+use bevy_matchbox::prelude::PeerId;
+use bevy_rapier3d::prelude::*;
+use std::f32::consts::TAU;
+
+/// Rollback frames per second. Gameplay is defined per frame, never per second of wall-clock
+/// time, which differs between peers and between resimulations of the same frame.
+pub const FPS: usize = 60;
+
+pub const PLAYER_SIZE: f32 = 1.;
+pub const PROJECTILE_RADIUS: f32 = 0.05;
+/// Distance a projectile travels each frame.
+pub const PROJECTILE_SPEED: f32 = 5. / FPS as f32;
+pub const PROJECTILE_LIFETIME: u32 = 2 * FPS as u32;
+pub const EXPLOSION_LIFETIME: u32 = FPS as u32 / 5;
+
+pub type Config = GgrsConfig<ShipInput, PeerId>;
+
+/// How many frames GGRS predicts ahead of the confirmed inputs, and so the furthest back a
+/// rollback goes. Every session is built with it, see [`session_builder`].
+pub const MAX_PREDICTION: usize = 8;
+/// GGRS only takes a check distance below its prediction window.
+pub const MIN_CHECK_DISTANCE: usize = 2;
+pub const MAX_CHECK_DISTANCE: usize = MAX_PREDICTION - 1;
+
+/// Starts every session, so that they all roll back as far as [`MAX_PREDICTION`] says.
+pub fn session_builder() -> ggrs::SessionBuilder<Config> {
+    ggrs::SessionBuilder::new()
+        .with_max_prediction_window(MAX_PREDICTION)
+        .expect("the prediction window is never empty")
+}
+
+/// The gameplay systems in `GgrsSchedule`, and the state they roll back. Spawns nothing that
+/// needs a renderer, so it runs headless too.
+pub struct SimulationPlugin;
+
+#[derive(SystemSet, Clone, PartialEq, Eq, Debug, Hash)]
+pub struct SimulationSet;
+
+impl Plugin for SimulationPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(PhysicsPlugin)
+            .set_rollback_schedule_fps(FPS)
+            .checked_rollback_with_clone::<Transform>()
+            .checked_rollback_with_copy::<Player>()
+            .checked_rollback_with_copy::<MoveDir>()
+            .checked_rollback_with_copy::<Projectile>()
+            .checked_rollback_with_copy::<EntityTTL>()
+            .checked_rollback_with_copy::<RigidBody>()
+            .checked_rollback_with_clone::<Collider>()
+            .checked_rollback_with_copy::<Sensor>()
+            .checksum_component::<Transform>(checksum)
+            .checksum_component::<Player>(checksum)
+            .checksum_component::<MoveDir>(checksum)
+            .checksum_component::<EntityTTL>(checksum)
+            // replaced with a seeded one whenever a session starts
+            .init_resource::<Rng>()
+            .rollback_resource_with_copy::<Rng>()
+            .checksum_resource::<Rng>(checksum)
+            .init_resource::<Effects>()
+            .add_systems(LoadWorld, mark_stale_effects)
+            .add_systems(Update, settle_effects)
+            .add_systems(
+                GgrsSchedule,
+                (
+                    rotate_players,
+                    fire_projectile.after(rotate_players),
+                    move_projectile.after(fire_projectile),
+                    handle_projectile_collision.after(move_projectile),
+                    kill_aged_entities.after(handle_projectile_collision),
+                )
+                    .in_set(SimulationSet),
+            )
+            // Recomputed every frame, but only where they exist, and GGRS respawns an entity with
+            // its rolled-back components alone. Without a `GlobalTransform`, Rapier would never
+            // move the body of a respawned projectile.
+            .checked_rollback_with_clone::<GlobalTransform>()
+            .checked_rollback_with_clone::<Visibility>()
+            .checked_rollback_with_clone::<InheritedVisibility>()
+            .checked_rollback_with_clone::<ViewVisibility>();
+        #[cfg(debug_assertions)]
+        app.add_systems(Last, crate::warn_unregistered_components);
+    }
+}
+
+/// Evenly spaces `count` spawn points on a ring around the origin, growing the ring with the
+/// player count so ships keep roughly the same spacing. Two players get the original
+/// `(-2, 0, 0)`, `(2, 0, 0)` layout.
+pub fn spawn_position(handle: usize, count: usize) -> Vec3 {
+    let radius = 2. * (count as f32 / 2.).sqrt();
+    let angle = TAU * handle as f32 / count as f32;
+    Vec3::new(-angle.cos(), 0., angle.sin()) * radius
+}
+
+/// Where `handle` starts, facing the middle.
+pub fn spawn_transform(handle: usize, count: usize) -> Transform {
+    Transform::from_translation(spawn_position(handle, count)).looking_at(Vec3::ZERO, Vec3::Y)
+}
+
+/// What Rapier simulates of a ship, the same in the game and headless.
+pub fn ship_body() -> (RigidBody, Sensor, Collider) {
+    let half_size = PLAYER_SIZE / 2.;
+    (
+        RigidBody::Dynamic,
+        Sensor,
+        Collider::cuboid(half_size, half_size, half_size),
+    )
+}
+
+/// Whether a sphere overlaps a cube of side `size` placed and oriented by `cube`.
+fn sphere_hits_cube(center: Vec3, radius: f32, cube: &Transform, size: f32) -> bool {
+    let half_extents = Vec3::splat(size / 2.);
+    let local = cube.rotation.inverse() * (center - cube.translation);
+    let closest = local.clamp(-half_extents, half_extents);
+    local.distance_squared(closest) <= radius * radius
+}
+
+/// Runs on the rolled-back transforms, so a resimulated frame hits exactly what it hit the
+/// first time, on every peer.
+fn handle_projectile_collision(
+    projectiles: Query<(Entity, &Rollback, &Transform), With<Projectile>>,
+    players: Query<&Transform, With<Player>>,
+    frame: Res<RollbackFrameCount>,
+    mut effects: ResMut<Effects>,
+    mut commands: Commands,
+) {
+    for (entity, &rollback, &transform) in &projectiles {
+        let hit = players.iter().any(|player| {
+            sphere_hits_cube(
+                transform.translation,
+                PROJECTILE_RADIUS,
+                player,
+                PLAYER_SIZE,
+            )
+        });
+        if hit {
+            commands.entity(entity).despawn_recursive();
+            effects.request(
+                &mut commands,
+                *frame,
+                rollback,
+                EffectKind::Explosion,
+                transform,
+                EXPLOSION_LIFETIME,
+            );
+        }
+    }
+}
+
+fn kill_aged_entities(mut entities: Query<(Entity, &mut EntityTTL)>, mut commands: Commands) {
+    for (entity, mut entity_ttl) in entities.iter_mut() {
+        entity_ttl.0 = entity_ttl.0.saturating_sub(1);
+        if entity_ttl.0 == 0 {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+fn rotate_players(
+    mut players: Query<(&mut Transform, &Player)>,
+    inputs: Res<PlayerInputs<Config>>,
+) {
+    for (mut transform, player) in &mut players {
+        let (input, _) = inputs[player.handle];
+        let rotation = input.rotation();
+        transform.rotate(Quat::from_euler(
+            EulerRot::XYZ,
+            rotation.x,
+            rotation.y,
+            rotation.z,
+        ));
+    }
+}
+
+fn fire_projectile(
+    mut commands: Commands,
+    inputs: Res<PlayerInputs<Config>>,
+    mut players: Query<(&Transform, &Player)>,
+) {
+    for (&transform, player) in &mut players {
+        let (input, _) = inputs[player.handle];
+        if input.fire() {
+            let forward = -transform.local_z();
+            let spawn_location = transform.translation + forward * PLAYER_SIZE * 1.65;
+            commands
+                .spawn((
+                    EntityTTL(PROJECTILE_LIFETIME),
+                    RigidBody::Dynamic,
+                    Collider::ball(PROJECTILE_RADIUS),
+                    Sensor,
+                    Projectile,
+                    MoveDir(forward),
+                    SpatialBundle::from_transform(Transform::from_translation(spawn_location)),
+                ))
+                .add_rollback();
+        }
+    }
+}
+
+fn move_projectile(mut projectile: Query<(&mut Transform, &MoveDir), With<Projectile>>) {
+    for (mut transform, dir) in &mut projectile {
+        transform.translation += dir.0 * PROJECTILE_SPEED;
+    }
+}
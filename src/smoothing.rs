@@ -0,0 +1,127 @@
+//! Keeps ships moving smoothly on screen while the simulation stays exact. Between the
+//! simulation's fixed frames a ship is drawn partway from the previous frame to the latest, and
+//! when a rollback corrects a ship, mostly a remote one, the jump is spread over a few frames.
+//!
+//! Drawing goes by `Transform`, so the drawn transform takes its place after `Update` and the
+//! simulated one is put back at the start of the next frame, before GGRS runs.
+
+use crate::FPS;
+use bevy::prelude::*;
+use bevy_ggrs::RollbackFrameCount;
+use std::collections::VecDeque;
+
+/// How long a correction takes to blend away.
+const CORRECTION_SECONDS: f32 = 6. / FPS as f32;
+/// Simulated frames kept to compare a resimulation with, more than GGRS ever rolls back.
+const HISTORY: usize = 16;
+
+/// Not rolled back: it remembers what each frame looked like before a rollback changed it.
+#[derive(Component)]
+pub struct Smoothing {
+    /// The simulated transform of each recent frame.
+    history: VecDeque<(i32, Transform)>,
+    /// The simulated transform of the frame before the latest.
+    from: Transform,
+    /// The latest simulated transform, which `First` puts back.
+    to: Transform,
+    /// How far the drawn ship still is from where the simulation puts it, after corrections.
+    offset: Vec3,
+    turn: Quat,
+}
+
+impl Smoothing {
+    pub fn new(transform: Transform) -> Self {
+        Self {
+            history: VecDeque::with_capacity(HISTORY),
+            from: transform,
+            to: transform,
+            offset: Vec3::ZERO,
+            turn: Quat::IDENTITY,
+        }
+    }
+
+    fn interpolate(&self, fraction: f32) -> Transform {
+        Transform {
+            translation: self.from.translation.lerp(self.to.translation, fraction),
+            rotation: self.from.rotation.slerp(self.to.rotation, fraction),
+            scale: self.to.scale,
+        }
+    }
+}
+
+/// Records each simulated frame, in `GgrsSchedule` once the frame is done. A resimulated frame
+/// that comes out differently keeps the ship drawn where it was, for the difference to blend
+/// away.
+pub fn track_simulated_transforms(
+    mut ships: Query<(&Transform, &mut Smoothing)>,
+    frame: Res<RollbackFrameCount>,
+) {
+    let frame = i32::from(*frame);
+    for (&transform, mut smoothing) in &mut ships {
+        match smoothing.history.iter().position(|&(f, _)| f == frame) {
+            Some(index) => {
+                let before = std::mem::replace(&mut smoothing.history[index].1, transform);
+                // only the latest frame before the rollback is the one that was drawn
+                if index + 1 == smoothing.history.len() {
+                    smoothing.offset += before.translation - transform.translation;
+                    smoothing.turn =
+                        smoothing.turn * before.rotation * transform.rotation.inverse();
+                }
+            }
+            None => {
+                if smoothing.history.len() == HISTORY {
+                    smoothing.history.pop_front();
+                }
+                smoothing.history.push_back((frame, transform));
+            }
+        }
+    }
+}
+
+/// Where the simulation is, as of the last drawn frame.
+#[derive(Default)]
+pub struct SimulationClock {
+    frame: i32,
+    /// Since `frame` was simulated.
+    elapsed: f32,
+}
+
+/// Puts the simulated transforms back, in `First`.
+pub fn restore_simulated_transforms(mut ships: Query<(&mut Transform, &Smoothing)>) {
+    for (mut transform, smoothing) in &mut ships {
+        *transform = smoothing.to;
+    }
+}
+
+/// Swaps in the drawn transforms, in `PostUpdate` before they are propagated.
+pub fn smooth_transforms(
+    mut ships: Query<(&mut Transform, &mut Smoothing)>,
+    frame: Res<RollbackFrameCount>,
+    mut clock: Local<SimulationClock>,
+    time: Res<Time>,
+) {
+    let frame = i32::from(*frame);
+    if frame != clock.frame {
+        clock.frame = frame;
+        clock.elapsed = 0.;
+    } else {
+        clock.elapsed += time.delta_seconds();
+    }
+    let fraction = (clock.elapsed * FPS as f32).min(1.);
+    let decay = (time.delta_seconds() / CORRECTION_SECONDS).min(1.);
+    for (mut transform, mut smoothing) in &mut ships {
+        smoothing.to = *transform;
+        smoothing.from = match smoothing.history.iter().rev().nth(1) {
+            Some(&(_, previous)) => previous,
+            None => smoothing.to,
+        };
+        let simulated = smoothing.interpolate(fraction);
+        *transform = Transform {
+            translation: simulated.translation + smoothing.offset,
+            rotation: (smoothing.turn * simulated.rotation).normalize(),
+            scale: simulated.scale,
+        };
+        smoothing.offset *= 1. - decay;
+        smoothing.turn = smoothing.turn.slerp(Quat::IDENTITY, decay);
+    }
+}
@@ -0,0 +1,69 @@
+//! Meshes and materials built once and shared by everything spawned, so that spawning, and
+//! respawning while resimulating, never adds assets.
+
+use crate::{MAX_PLAYERS, PLAYER_COLORS, PLAYER_SIZE, PROJECTILE_RADIUS};
+use bevy::prelude::*;
+
+pub const BARREL_LENGTH: f32 = PLAYER_SIZE;
+const BARREL_RADIUS: f32 = 0.05 * PLAYER_SIZE;
+
+#[derive(Resource)]
+pub struct SpawnAssets {
+    pub projectile_mesh: Handle<Mesh>,
+    pub projectile_material: Handle<StandardMaterial>,
+    pub explosion_mesh: Handle<Mesh>,
+    pub explosion_material: Handle<StandardMaterial>,
+    pub player_mesh: Handle<Mesh>,
+    /// By handle.
+    pub player_materials: [Handle<StandardMaterial>; MAX_PLAYERS],
+    /// The X, Y and Z markers on each ship.
+    pub marker_mesh: Handle<Mesh>,
+    pub marker_materials: [Handle<StandardMaterial>; 3],
+    pub barrel_mesh: Handle<Mesh>,
+    pub white_material: Handle<StandardMaterial>,
+    pub origin_marker_mesh: Handle<Mesh>,
+    /// Along the axes, every unit from the origin.
+    pub axis_marker_mesh: Handle<Mesh>,
+}
+
+fn sphere(radius: f32) -> Mesh {
+    Mesh::try_from(shape::Icosphere {
+        radius,
+        ..Default::default()
+    })
+    .unwrap()
+}
+
+impl FromWorld for SpawnAssets {
+    fn from_world(world: &mut World) -> Self {
+        let mut meshes = world.resource_mut::<Assets<Mesh>>();
+        let projectile_mesh = meshes.add(sphere(PROJECTILE_RADIUS));
+        let explosion_mesh = meshes.add(sphere(PLAYER_SIZE / 5.));
+        let player_mesh = meshes.add(Mesh::from(shape::Cube { size: PLAYER_SIZE }));
+        let marker_mesh = meshes.add(sphere(PLAYER_SIZE / 6.8));
+        let barrel_mesh = meshes.add(Mesh::from(shape::Capsule {
+            radius: BARREL_RADIUS,
+            depth: BARREL_LENGTH,
+            ..Default::default()
+        }));
+        let origin_marker_mesh = meshes.add(sphere(0.03));
+        let axis_marker_mesh = meshes.add(sphere(0.025));
+
+        let mut materials = world.resource_mut::<Assets<StandardMaterial>>();
+        Self {
+            projectile_mesh,
+            projectile_material: materials.add(Color::RED.into()),
+            explosion_mesh,
+            explosion_material: materials.add(Color::PINK.into()),
+            player_mesh,
+            player_materials: PLAYER_COLORS.map(|color| materials.add(color.into())),
+            marker_mesh,
+            marker_materials: [Color::RED, Color::GREEN, Color::BLUE]
+                .map(|color| materials.add(color.into())),
+            barrel_mesh,
+            white_material: materials.add(Color::WHITE.into()),
+            origin_marker_mesh,
+            axis_marker_mesh,
+        }
+    }
+}
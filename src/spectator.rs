@@ -0,0 +1,65 @@
+use bevy::prelude::*;
+use std::f32::consts::TAU;
+
+/// A camera that isn't attached to any ship. WASD to move, R/F for up/down, the arrow keys to
+/// look around and shift to go faster.
+#[derive(Component)]
+pub struct FreeCamera;
+
+pub fn spawn_free_camera(mut commands: Commands) {
+    commands.spawn((
+        FreeCamera,
+        Camera3dBundle {
+            transform: Transform::from_xyz(0., 6., 12.).looking_at(Vec3::ZERO, Vec3::Y),
+            ..default()
+        },
+    ));
+}
+
+pub fn move_free_camera(
+    mut cameras: Query<&mut Transform, With<FreeCamera>>,
+    keys: Res<Input<KeyCode>>,
+    time: Res<Time>,
+) {
+    let mut movement = Vec3::ZERO;
+    for (key, direction) in [
+        (KeyCode::W, Vec3::NEG_Z),
+        (KeyCode::S, Vec3::Z),
+        (KeyCode::A, Vec3::NEG_X),
+        (KeyCode::D, Vec3::X),
+        (KeyCode::R, Vec3::Y),
+        (KeyCode::F, Vec3::NEG_Y),
+    ] {
+        if keys.pressed(key) {
+            movement += direction;
+        }
+    }
+    let mut yaw = 0.;
+    let mut pitch = 0.;
+    for (key, yaw_delta, pitch_delta) in [
+        (KeyCode::Left, 1., 0.),
+        (KeyCode::Right, -1., 0.),
+        (KeyCode::Up, 0., 1.),
+        (KeyCode::Down, 0., -1.),
+    ] {
+        if keys.pressed(key) {
+            yaw += yaw_delta;
+            pitch += pitch_delta;
+        }
+    }
+
+    let speed = if keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]) {
+        12.
+    } else {
+        4.
+    };
+    let turn_speed = TAU / 4.;
+    let delta = time.delta_seconds();
+    for mut transform in &mut cameras {
+        let movement = transform.rotation * movement.normalize_or_zero();
+        transform.translation += movement * speed * delta;
+        // yaw around the world's up so the horizon stays level
+        transform.rotate_y(yaw * turn_speed * delta);
+        transform.rotate_local_x(pitch * turn_speed * delta);
+    }
+}
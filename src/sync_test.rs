@@ -0,0 +1,72 @@
+use crate::{session_builder, ExitFailure, GameState, Mode, Rng, Settings};
+use bevy::{app::AppExit, prelude::*};
+use bevy_ggrs::{prelude::*, RollbackFrameCount};
+use std::collections::BTreeMap;
+
+/// How many frames of checksums to remember. Comfortably more than any check distance GGRS
+/// accepts.
+const HISTORY_LENGTH: i32 = 128;
+
+/// The checksum each frame had the first time it was saved.
+#[derive(Resource, Default)]
+pub struct ChecksumHistory(BTreeMap<i32, u128>);
+
+pub fn start_synctest_session(
+    mut commands: Commands,
+    settings: Res<Settings>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut exit: EventWriter<AppExit>,
+) {
+    let Mode::SyncTest { check_distance, .. } = settings.mode else {
+        return;
+    };
+
+    let session = match session_builder()
+        .with_num_players(settings.players)
+        .with_check_distance(check_distance)
+        .with_input_delay(2)
+        .start_synctest_session()
+    {
+        Ok(session) => session,
+        Err(err) => {
+            error!("failed to start synctest session with check distance {check_distance}: {err}");
+            commands.insert_resource(ExitFailure);
+            exit.send(AppExit);
+            return;
+        }
+    };
+
+    info!(
+        "started synctest session for {} players, check distance {check_distance}",
+        settings.players
+    );
+    commands.insert_resource(ChecksumHistory::default());
+    // always the same, so a run can be repeated
+    commands.insert_resource(Rng::default());
+    commands.insert_resource(bevy_ggrs::Session::SyncTest(session));
+    next_state.set(GameState::InGame);
+}
+
+/// A SyncTest session resimulates the last few frames every frame, so every frame is saved
+/// several times with the same inputs. Any difference between those saves is nondeterminism.
+pub fn check_resimulated_checksums(
+    frame: Res<RollbackFrameCount>,
+    checksum: Res<Checksum>,
+    mut history: ResMut<ChecksumHistory>,
+) {
+    let frame = i32::from(*frame);
+    let first = *history.0.entry(frame).or_insert(checksum.0);
+    if first != checksum.0 {
+        error!(
+            "Desync in frame {frame}: resimulating it gave checksum {:032X}, it was first {first:032X}",
+            checksum.0
+        );
+    }
+
+    while let Some((&oldest, _)) = history.0.first_key_value() {
+        if oldest > frame - HISTORY_LENGTH {
+            break;
+        }
+        history.0.remove(&oldest);
+    }
+}